@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use budget::{CancellationToken, SearchBudget, SearchLimits, SearchStatus};
+use filters::{PartitionsSubsetSumsFilter, SubsetSumsFilter};
+use partition::{ParallelSumFilteredPartitions, SumFilteredPartitionIterator, MAX_SET_SIZE};
+use types::{Filter, Pairing, Partition, Set};
+
+#[cfg(test)]
+mod test;
+
+/// Options of an `Analyzer`. The default is an unbounded, sequential search.
+#[derive(Clone, Default)]
+pub struct AnalyzerOptions {
+    pub limits: SearchLimits,
+    /// Cancelling the token stops all analyses running with these options.
+    pub cancellation: CancellationToken,
+    /// Enumerate the partitions of a single transaction on the rayon thread
+    /// pool. Not worth it when many transactions are analyzed in parallel.
+    pub parallel: bool,
+}
+
+/// The mappings found for a transaction.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Analysis {
+    pub partition_tuples: Vec<(Partition, Partition)>,
+    /// The pairing of the parts of each entry of `partition_tuples`.
+    pub pairings: Vec<Pairing>,
+    pub search_status: SearchStatus,
+    pub duration: Duration,
+}
+
+/// Finds all mappings of the inputs of a CoinJoin transaction to its outputs.
+///
+/// First all partitions of the inputs whose parts sum up to a subset sum of
+/// the outputs are enumerated, then all partitions of the outputs whose parts
+/// match a part of one of these input partitions. Finally, input and output
+/// partitions are paired if their parts can be matched one to one, see
+/// `match_partitions`.
+pub struct Analyzer {
+    options: AnalyzerOptions,
+}
+
+impl Analyzer {
+    pub fn new(options: AnalyzerOptions) -> Analyzer {
+        Analyzer { options }
+    }
+
+    /// Transactions with more inputs or outputs than `MAX_SET_SIZE` or the
+    /// `max_coins` limit are not searched, their status is `TooManyCoins`.
+    pub fn analyze(&self, inputs: &Set, outputs: &Set) -> Analysis {
+        let now = Instant::now();
+        let max_coins = self
+            .options
+            .limits
+            .max_coins
+            .map_or(MAX_SET_SIZE, |max| (max as usize).min(MAX_SET_SIZE));
+        if inputs.len() > max_coins || outputs.len() > max_coins {
+            return Analysis {
+                partition_tuples: Vec::new(),
+                pairings: Vec::new(),
+                search_status: SearchStatus::TooManyCoins,
+                duration: now.elapsed(),
+            };
+        }
+        let budget = SearchBudget::new(
+            self.options.limits.clone(),
+            self.options.cancellation.clone(),
+        );
+        let in_partitions: Vec<Partition> = {
+            let filter = SubsetSumsFilter::with_budget(outputs, &budget);
+            self.partitions(inputs, &filter, &budget)
+        };
+        let out_partitions: Vec<Partition> = {
+            let filter = PartitionsSubsetSumsFilter::new(&in_partitions);
+            self.partitions(outputs, &filter, &budget)
+        };
+        let mut partition_tuples: Vec<(Partition, Partition)> = Vec::new();
+        let mut pairings: Vec<Pairing> = Vec::new();
+        'outer: for in_partition in in_partitions.iter() {
+            for out_partition in out_partitions.iter() {
+                if budget.exhausted() {
+                    break 'outer;
+                }
+                if let Some(pairing) = match_partitions(in_partition, out_partition) {
+                    partition_tuples.push((in_partition.clone(), out_partition.clone()));
+                    pairings.push(pairing);
+                }
+            }
+        }
+        Analysis {
+            partition_tuples,
+            pairings,
+            search_status: budget.status(),
+            duration: now.elapsed(),
+        }
+    }
+
+    fn partitions<F>(&self, set: &Set, filter: &F, budget: &SearchBudget) -> Vec<Partition>
+    where
+        F: Filter<u64> + Sync,
+    {
+        if self.options.parallel {
+            ParallelSumFilteredPartitions::with_budget(set.clone(), filter, budget)
+                .collect_parallel()
+        } else {
+            SumFilteredPartitionIterator::with_budget(set.clone(), filter, budget).collect()
+        }
+    }
+}
+
+impl Default for Analyzer {
+    fn default() -> Analyzer {
+        Analyzer::new(AnalyzerOptions::default())
+    }
+}
+
+/// Matches the parts of partition `a` one to one to the parts of partition `b`
+/// with the same sum, returning the pairs of part indices ordered by the parts
+/// of `a`. Returns `None` if the multi-sets of part sums differ.
+///
+/// Parts with equal sums are interchangeable, they are paired in the order in
+/// which they appear in their partitions.
+pub fn match_partitions(a: &Partition, b: &Partition) -> Option<Pairing> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let b_sums: Vec<u64> = b.iter().map(|set| set.iter().sum()).collect();
+    let mut b_used = vec![false; b.len()];
+    let mut pairing = Pairing::with_capacity(a.len());
+    for (i, set_a) in a.iter().enumerate() {
+        let sum_a: u64 = set_a.iter().sum();
+        let j = (0..b.len()).find(|&j| !b_used[j] && b_sums[j] == sum_a)?;
+        b_used[j] = true;
+        pairing.push((i, j));
+    }
+    Some(pairing)
+}
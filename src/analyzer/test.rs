@@ -0,0 +1,136 @@
+use serde_json;
+
+use super::*;
+use budget::SearchStatus;
+use counting::count_mappings;
+use types::Run;
+
+#[test]
+fn test_analyze() {
+    let analysis = Analyzer::default().analyze(&vec![1, 3, 18], &vec![3, 4, 15]);
+    assert_eq!(analysis.search_status, SearchStatus::Complete);
+    assert_eq!(
+        analysis.partition_tuples,
+        vec![
+            (vec![vec![3], vec![1, 18]], vec![vec![4, 15], vec![3]]),
+            (vec![vec![18], vec![1, 3]], vec![vec![4], vec![3, 15]]),
+            (vec![vec![1, 3, 18]], vec![vec![3, 4, 15]]),
+        ]
+    );
+    assert_eq!(
+        analysis.pairings,
+        vec![vec![(0, 1), (1, 0)], vec![(0, 1), (1, 0)], vec![(0, 0)]]
+    );
+}
+
+#[test]
+fn test_match_partitions() {
+    assert_eq!(
+        match_partitions(&vec![vec![1, 2], vec![4]], &vec![vec![4], vec![3]]),
+        Some(vec![(0, 1), (1, 0)])
+    );
+    // Two input parts must not be matched to the same output part.
+    assert_eq!(
+        match_partitions(&vec![vec![1, 2], vec![3]], &vec![vec![3, 3]]),
+        None
+    );
+    assert_eq!(
+        match_partitions(&vec![vec![1, 2], vec![3]], &vec![vec![3], vec![2, 1]]),
+        Some(vec![(0, 0), (1, 1)])
+    );
+    // Every output part needs a partner as well.
+    assert_eq!(
+        match_partitions(&vec![vec![3], vec![3]], &vec![vec![3], vec![3], vec![3]]),
+        None
+    );
+    assert_eq!(
+        match_partitions(&vec![vec![2], vec![3]], &vec![vec![2], vec![4]]),
+        None
+    );
+}
+
+#[test]
+fn test_analyze_agrees_with_count_mappings() {
+    let test_files = vec![
+        include_str!("../partition/result-none-t-3-s-2-r-1.json"),
+        include_str!("../partition/result-output-t-3-s-2-r-1.json"),
+        include_str!("../partition/result-input-t-3-s-2-r-1.json"),
+    ];
+    for file in test_files {
+        let run: Run = serde_json::from_str(file).expect("Invalid json in input");
+        let analysis = Analyzer::default().analyze(&run.in_coins, &run.out_coins);
+        assert_eq!(
+            analysis.partition_tuples.len() as u64,
+            count_mappings(&run.in_coins, &run.out_coins).mappings
+        );
+        assert_eq!(analysis.pairings.len(), analysis.partition_tuples.len());
+    }
+}
+
+#[test]
+fn test_analyze_parallel() {
+    let parallel = Analyzer::new(AnalyzerOptions {
+        parallel: true,
+        ..AnalyzerOptions::default()
+    });
+    let test_files = vec![
+        include_str!("../partition/result-none-t-3-s-2-r-1.json"),
+        include_str!("../partition/result-output-t-3-s-2-r-1.json"),
+        include_str!("../partition/result-input-t-3-s-2-r-1.json"),
+    ];
+    for file in test_files {
+        let run: Run = serde_json::from_str(file).expect("Invalid json in input");
+        assert_eq!(
+            parallel
+                .analyze(&run.in_coins, &run.out_coins)
+                .partition_tuples,
+            Analyzer::default()
+                .analyze(&run.in_coins, &run.out_coins)
+                .partition_tuples
+        );
+    }
+}
+
+#[test]
+fn test_analyze_cancelled() {
+    let options = AnalyzerOptions::default();
+    options.cancellation.cancel();
+    let analysis = Analyzer::new(options).analyze(&vec![1, 3, 18], &vec![3, 4, 15]);
+    assert!(analysis.partition_tuples.is_empty());
+    assert_eq!(analysis.search_status, SearchStatus::Cancelled);
+}
+
+#[test]
+fn test_analyze_timeout_covers_filter() {
+    let coins: Set = (1..=40).map(|coin| coin * 1000 + coin).collect();
+    let analyzer = Analyzer::new(AnalyzerOptions {
+        limits: SearchLimits {
+            max_duration: Some(Duration::from_millis(50)),
+            ..SearchLimits::unlimited()
+        },
+        ..AnalyzerOptions::default()
+    });
+    let analysis = analyzer.analyze(&coins, &coins);
+    assert!(analysis.duration < Duration::from_secs(10));
+    assert_eq!(analysis.search_status, SearchStatus::TimedOut);
+}
+
+#[test]
+fn test_analyze_too_many_coins() {
+    let coins: Set = (1..=70).collect();
+    let analysis = Analyzer::default().analyze(&coins, &vec![coins.iter().sum()]);
+    assert!(analysis.partition_tuples.is_empty());
+    assert_eq!(analysis.search_status, SearchStatus::TooManyCoins);
+    let analyzer = Analyzer::new(AnalyzerOptions {
+        limits: SearchLimits {
+            max_coins: Some(2),
+            ..SearchLimits::unlimited()
+        },
+        ..AnalyzerOptions::default()
+    });
+    let analysis = analyzer.analyze(&vec![1, 3, 18], &vec![3, 19]);
+    assert_eq!(analysis.search_status, SearchStatus::TooManyCoins);
+    let analysis = analyzer.analyze(&vec![3, 18], &vec![3, 18]);
+    assert_eq!(analysis.search_status, SearchStatus::Complete);
+    assert_eq!(analysis.partition_tuples.len(), 2);
+}
@@ -1,97 +1,302 @@
 extern crate coinjoin_analyzer;
-use coinjoin_analyzer::{BlockFileIterator, Distribution};
+use coinjoin_analyzer::{
+    expand_block_files, locate_main_chain, BlockLocation, ChainIterator, CoinCounts, Distribution,
+    Progress, ShapeHistogram, UtxoSet,
+};
+#[macro_use(value_t)]
+extern crate clap;
 extern crate rmp_serde;
 extern crate serde;
+extern crate serde_json;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use rmp_serde::Serializer;
-use std::env::args;
 use std::error::Error;
 use std::fs;
-use std::fs::OpenOptions;
-use std::io::BufWriter;
-use std::io::Write;
-use std::path::Path;
+use std::fs::File;
+use std::io::{stdout, BufReader};
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let max_coin_value = 100_000_000_000;
-    let bucket_size = 100;
-    let mut buckets: std::collections::BTreeMap<u64, f64> = std::collections::BTreeMap::new();
-
-    println!("Parsing blocks");
-    let num_files = (args().len() - 1) as f64;
-    let mut current_file = 1f64;
-    for file in args().skip(1) {
-        let iter = match BlockFileIterator::open(file) {
-            Ok(i) => i,
-            Err(_) => panic!("Could not read file"),
-        };
-        print!("{:.0}% ", current_file / num_files * 100f64);
-        for block in iter {
-            print!(".");
-            let _ = std::io::stdout().flush();
-            for transaction in block.transactions.iter() {
-                for output in transaction.outputs.iter() {
-                    if output.value > max_coin_value {
-                        continue;
-                    }
-                    let bucket = output.value as u64 / bucket_size;
-                    if let std::collections::btree_map::Entry::Vacant(e) = buckets.entry(bucket) {
-                        e.insert(1f64);
-                    } else {
-                        let v = buckets
-                            .get_mut(&bucket)
-                            .expect("Unable to get key which buckets contain");
-                        *v += 1f64;
-                    }
-                }
-            }
+    let matches = get_app().get_matches();
+    let (counts, options) = match matches.subcommand() {
+        ("merge", Some(options)) => (merge(options)?, options),
+        _ if matches.is_present("unspent-at") => (unspent(&matches)?, &matches),
+        _ => (update(&matches)?, &matches),
+    };
+    println!(
+        "Counted {} coins of the heights {}",
+        counts.total(),
+        counts
+            .heights
+            .iter()
+            .map(|(first, last)| format!("{}-{}", first, last))
+            .collect::<Vec<String>>()
+            .join(",")
+    );
+    save(options.value_of("counts").unwrap(), &counts, false)?;
+    // Without coins the cumulative distribution divides by zero, and sampling
+    // the written file would fail much later.
+    if counts.total() == 0 {
+        return Err(NO_COINS.into());
+    }
+    println!("Writing result");
+    // The minimum share of all coins of a point mass.
+    let dist = counts.to_distribution(0.000_001);
+    println!("Found {} point masses", dist.point_masses.len());
+    let json = options.value_of("format") == Some("json");
+    if let Some(file) = options.value_of("shapes") {
+        let shapes = counts.shapes.as_ref().ok_or(SHAPES_MISSING)?;
+        println!("Found {} transaction shapes", shapes.counts.len());
+        save::<ShapeHistogram>(file, shapes, json)?;
+    }
+    save::<Distribution>(options.value_of("output").unwrap(), &dist, json)
+}
+
+const NO_COINS: &str = "No coins were counted, so there is no distribution to write";
+
+const SHAPES_MISSING: &str =
+    "The counts were written before transaction shapes were counted, build them again to write shapes";
+
+fn output_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .takes_value(true)
+            .default_value("distribution.bin")
+            .help("file to write the distribution to"),
+        Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .default_value("msgpack")
+            .possible_values(&["msgpack", "json"])
+            .help("format of the distribution file"),
+        Arg::with_name("shapes")
+            .long("shapes")
+            .takes_value(true)
+            .help(
+            "also write how many transactions have each number of inputs and outputs to this file",
+        ),
+        Arg::with_name("force")
+            .short("f")
+            .long("force")
+            .help("overwrite an existing distribution file"),
+    ]
+}
+
+fn get_app<'a>() -> App<'a, 'a> {
+    App::new("build_distribution")
+        .author("Felix Konstantin Maurer <maufl@maufl.de>")
+        .about("This program builds a coin size distribution from the outputs in bitcoind block files.")
+        .version("v0.1")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(
+            Arg::with_name("blocks")
+                .multiple(true)
+                .required(true)
+                .help("block files, directories with blk*.dat files or patterns like blocks/blk00*.dat"),
+        )
+        .arg(
+            Arg::with_name("counts")
+                .long("counts")
+                .takes_value(true)
+                .default_value("counts.bin")
+                .help("file of the raw coin counts, updated with the blocks it does not cover yet"),
+        )
+        .arg(
+            Arg::with_name("confirmations")
+                .long("confirmations")
+                .takes_value(true)
+                .default_value("6")
+                .help("leave out the newest blocks that have fewer confirmations, since they may still be replaced"),
+        )
+        .arg(
+            Arg::with_name("unspent-at")
+                .long("unspent-at")
+                .takes_value(true)
+                .help("only count the coins unspent at this height, which needs a lot of memory"),
+        )
+        .args(&output_args())
+        .subcommand(
+            SubCommand::with_name("merge")
+                .about("merge count files of different block heights")
+                .arg(Arg::with_name("files").multiple(true).required(true).min_values(2))
+                .arg(
+                    Arg::with_name("counts")
+                        .long("counts")
+                        .takes_value(true)
+                        .default_value("counts.bin")
+                        .help("file to write the merged counts to"),
+                )
+                .args(&output_args()),
+        )
+}
+
+/// Fails early if an output would be overwritten without `--force`. The count
+/// file is only checked if it is not meant to be updated.
+fn check_overwrite(options: &ArgMatches, counts: bool) -> Result<(), Box<dyn Error>> {
+    let mut files = vec![options.value_of("output"), options.value_of("shapes")];
+    if counts {
+        files.push(options.value_of("counts"));
+    }
+    for file in files.into_iter().flatten() {
+        if Path::new(file).exists() && !options.is_present("force") {
+            return Err(format!("{} already exists, use --force to overwrite it", file).into());
         }
-        println!();
-        current_file += 1f64;
     }
+    Ok(())
+}
 
-    println!("Cumulating buckets");
-    let mut previous = 0f64;
-    for value in buckets.values_mut() {
-        *value += previous;
-        previous = *value;
+/// Merges count files of different block heights.
+fn merge(options: &ArgMatches) -> Result<CoinCounts, Box<dyn Error>> {
+    check_overwrite(options, true)?;
+    let files: Vec<&str> = options.values_of("files").unwrap().collect();
+    let mut counts: CoinCounts = load_from_rmp(Path::new(files[0]))?;
+    for file in files[1..].iter() {
+        let other: CoinCounts = load_from_rmp(Path::new(file))?;
+        counts.merge(&other)?;
     }
-    println!("Normalizing buckets");
-    for value in buckets.values_mut() {
-        *value /= previous;
+    Ok(counts)
+}
+
+/// Counts the coins of the blocks of the main chain that the count file does
+/// not cover yet.
+fn update(options: &ArgMatches) -> Result<CoinCounts, Box<dyn Error>> {
+    check_overwrite(options, false)?;
+    let counts_file = Path::new(options.value_of("counts").unwrap());
+    let mut counts: CoinCounts = if counts_file.exists() {
+        println!("Updating {}", counts_file.display());
+        load_from_rmp(counts_file)?
+    } else {
+        new_counts()
+    };
+    if let Some(height) = counts.unspent_at {
+        return Err(format!(
+            "{} holds the coins unspent at height {} and can't be updated",
+            counts_file.display(),
+            height
+        )
+        .into());
     }
-    println!("Writing result");
-    let dist = Distribution::new(
-        buckets
-            .iter()
-            .map(|(key, value)| (*key * bucket_size, *value))
-            .collect(),
-    );
-    save_to_rmp::<Distribution>(Path::new("distribution.bin"), &dist)
+    // Fail before parsing blocks, since the shapes of the counted blocks are missing.
+    if options.is_present("shapes") && counts.shapes.is_none() {
+        return Err(SHAPES_MISSING.into());
+    }
+
+    let (files, chain) = read_main_chain(options)?;
+    counts
+        .check_tip(&chain)
+        .map_err(|error| format!("{}, {} has to be built again", error, counts_file.display()))?;
+    // The newest blocks may still be replaced by a reorganization.
+    let confirmations = value_t!(options.value_of("confirmations"), usize)?;
+    let confirmed = chain.len().saturating_sub(confirmations);
+    let new_blocks: Vec<(u64, usize, BlockLocation)> = chain
+        .into_iter()
+        .take(confirmed)
+        .enumerate()
+        .filter(|&(height, _)| !counts.covers(height as u64))
+        .map(|(height, (file, location))| (height as u64, file, location))
+        .collect();
+    let tip = new_blocks
+        .last()
+        .map(|&(height, _, ref location)| (height, location.hash));
+
+    let mut progress = Progress::new(stdout(), "Parsing blocks", new_blocks.len());
+    for block in ChainIterator::new(files, new_blocks) {
+        let (height, block) = block?;
+        counts.add_block(height, &block);
+        progress.advance();
+    }
+    if let Some((height, hash)) = tip {
+        counts.record_tip(height, &hash);
+    }
+    Ok(counts)
+}
+
+/// Counts the coins that are unspent at the height given by `--unspent-at`,
+/// by applying the blocks of the main chain in order.
+fn unspent(options: &ArgMatches) -> Result<CoinCounts, Box<dyn Error>> {
+    check_overwrite(options, true)?;
+    let height = value_t!(options.value_of("unspent-at"), usize)?;
+    let (files, chain) = read_main_chain(options)?;
+    if chain.len() <= height {
+        return Err(format!(
+            "The block files only reach height {}",
+            chain.len() as i64 - 1
+        )
+        .into());
+    }
+    let blocks = chain
+        .into_iter()
+        .take(height + 1)
+        .enumerate()
+        .map(|(height, (file, location))| (height as u64, file, location))
+        .collect();
+
+    let mut utxos = UtxoSet::new();
+    let mut shapes = ShapeHistogram::new();
+    let mut missing = 0;
+    let mut progress = Progress::new(stdout(), "Applying blocks", height + 1);
+    for block in ChainIterator::new(files, blocks) {
+        let (_, block) = block?;
+        missing += utxos.apply_block(&block);
+        shapes.add_block(&block);
+        progress.advance();
+    }
+    if missing > 0 {
+        eprintln!(
+            "Warning: {} inputs spend unknown coins, some blocks may be missing",
+            missing
+        );
+    }
+    println!("Found {} unspent coins", utxos.len());
+    let mut counts = new_counts();
+    counts.add_unspent(&utxos, height as u64);
+    counts.shapes = Some(shapes);
+    Ok(counts)
+}
+
+fn new_counts() -> CoinCounts {
+    CoinCounts::new(100, 100_000_000_000, 10_000)
 }
 
-/// Save Serializable data to a file as RustMessagePack format.
-/// Note if the file already exist. **The existing file is deleted.**
-/*
-pub async fn save_to<T>(file : &Path, data : &T) -> Result<(), Box<dyn Error>>
-    where T : Serialize{
-    save_to_rmp(file,data)
+/// Locates the blocks of the main chain in the block files given as
+/// arguments, ordered by height.
+fn read_main_chain(
+    options: &ArgMatches,
+) -> Result<(Vec<PathBuf>, Vec<(usize, BlockLocation)>), Box<dyn Error>> {
+    let arguments: Vec<&str> = options.values_of("blocks").unwrap().collect();
+    let files = expand_block_files(&arguments).map_err(|error| error.to_string())?;
+    let mut progress = Progress::new(stdout(), "Reading block headers", files.len());
+    let chain =
+        locate_main_chain(&files, || progress.advance()).map_err(|error| error.to_string())?;
+    Ok((files, chain))
 }
-*/
 
-fn save_to_rmp<T>(file: &Path, data: &T) -> Result<(), Box<dyn Error>>
+/// Writes the data as MessagePack, or JSON, to a temporary file that then
+/// replaces `file`, so that an interrupted write keeps the previous file.
+fn save<T>(file: &str, data: &T, json: bool) -> Result<(), Box<dyn Error>>
 where
     T: Serialize,
 {
-    if file.exists() {
-        fs::remove_file(file)?;
-    }
-    let file_handler = OpenOptions::new().write(true).create_new(true).open(file)?;
-
     let mut buf = Vec::new();
-    data.serialize(&mut Serializer::new(&mut buf))?;
-    let mut buf_writer = BufWriter::new(&file_handler);
-    buf_writer.write_all(buf.as_slice())?;
+    if json {
+        serde_json::to_writer(&mut buf, data)?;
+    } else {
+        data.serialize(&mut Serializer::new(&mut buf))?;
+    }
+    let partial = format!("{}.partial", file);
+    fs::write(&partial, buf)?;
+    fs::rename(&partial, file)?;
     Ok(())
 }
+
+fn load_from_rmp<T>(file: &Path) -> Result<T, Box<dyn Error>>
+where
+    T: DeserializeOwned,
+{
+    Ok(rmp_serde::from_read(BufReader::new(File::open(file)?))?)
+}
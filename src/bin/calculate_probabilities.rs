@@ -1,18 +1,134 @@
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
 
+extern crate rand;
+use rand::thread_rng;
+
+#[macro_use(value_t)]
+extern crate clap;
+use clap::{App, Arg};
+
 extern crate coinjoin_analyzer;
-use coinjoin_analyzer::{Partition, Run};
+use coinjoin_analyzer::{
+    filter_derived_partitions, read_results, AggregatedProbabilities, EntropyMode, Estimate,
+    GroundTruthScore, LinkProbabilities, PrivacyMetrics, Run, ScoreSummary, Summary,
+    LINK_CSV_HEADER,
+};
 
+use std::collections::BTreeMap;
 use std::io;
-use std::io::Read;
+use std::io::Write;
+
+/// The link probability matrices of one run, as printed with `--matrix json`.
+#[derive(Serialize)]
+struct RunLinks {
+    run: usize,
+    num_transactions: u64,
+    num_inputs_per_transaction: u64,
+    non_derived_mappings: usize,
+    links: LinkProbabilities,
+    metrics: PrivacyMetrics,
+}
 
 fn main() {
-    let mut input = String::new();
-    match io::stdin().read_to_string(&mut input) {
-        Ok(_) => (),
+    let matches = App::new("calculate_probabilities")
+        .about("Calculates link probabilities for the runs of a cja result read from stdin, in any of its result formats")
+        .arg(
+            Arg::with_name("matrix")
+                .long("matrix")
+                .takes_value(true)
+                .possible_values(&["json", "csv"])
+                .help("print the full link probability matrices of each run instead of aggregates"),
+        )
+        .arg(
+            Arg::with_name("entropy")
+                .long("entropy")
+                .takes_value(true)
+                .default_value("non-derived")
+                .possible_values(&["non-derived", "boltzmann"])
+                .help("which mappings count as interpretations for entropy and anonymity sets"),
+        )
+        .arg(
+            Arg::with_name("score")
+                .long("score")
+                .takes_value(true)
+                .possible_values(&["runs", "summary"])
+                .help(
+                    "score the mappings against the original transactions, per run or aggregated",
+                ),
+        )
+        .arg(Arg::with_name("summary").long("summary").help(
+            "print statistics over the runs of each configuration instead of one line per run",
+        ))
+        .arg(
+            Arg::with_name("resamples")
+                .long("resamples")
+                .takes_value(true)
+                .default_value("1000")
+                .help("number of bootstrap resamples for the confidence intervals of --summary"),
+        )
+        .get_matches();
+    let mode = match matches.value_of("entropy") {
+        Some("boltzmann") => EntropyMode::Boltzmann,
+        _ => EntropyMode::NonDerived,
+    };
+    let stdin = io::stdin();
+    let result: Vec<Run> = match read_results(stdin.lock()) {
+        Ok((_, runs)) => runs,
         Err(error) => return print!("Error while reading file: {}", error),
     };
-    let result: Vec<Run> = serde_json::from_str(input.as_str()).expect("Invalid json in input");
+    match (matches.value_of("matrix"), matches.value_of("score")) {
+        (Some("json"), _) => print_matrices_json(result, mode),
+        (Some("csv"), _) => print_matrices_csv(result, mode),
+        (_, Some("runs")) => print_scores(result),
+        (_, Some("summary")) => print_score_summary(result),
+        _ if matches.is_present("summary") => {
+            let resamples =
+                value_t!(matches.value_of("resamples"), usize).unwrap_or_else(|e| e.exit());
+            print_summary(result, resamples)
+        }
+        _ => print_aggregates(result, mode),
+    }
+}
+
+fn run_links(index: usize, run: &Run, mode: EntropyMode) -> RunLinks {
+    let non_derived_partitions = filter_derived_partitions(&run.partition_tuples);
+    RunLinks {
+        run: index,
+        num_transactions: run.num_transactions,
+        num_inputs_per_transaction: run.num_inputs_per_transaction,
+        non_derived_mappings: non_derived_partitions.len(),
+        links: LinkProbabilities::new(&run.in_coins, &run.out_coins, &non_derived_partitions),
+        metrics: PrivacyMetrics::new(&run.in_coins, &run.out_coins, &run.partition_tuples, mode),
+    }
+}
+
+fn print_matrices_json(result: Vec<Run>, mode: EntropyMode) {
+    let links: Vec<RunLinks> = result
+        .iter()
+        .enumerate()
+        .map(|(index, run)| run_links(index, run, mode))
+        .collect();
+    println!(
+        "{}",
+        serde_json::to_string(&links).expect("Unable to serialize link probabilities")
+    );
+}
+
+fn print_matrices_csv(result: Vec<Run>, mode: EntropyMode) {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let _ = writeln!(out, "{}", LINK_CSV_HEADER);
+    for (index, run) in result.iter().enumerate() {
+        if let Err(error) = run_links(index, run, mode).links.write_csv(&mut out, index) {
+            return eprintln!("Error while writing link probabilities: {}", error);
+        }
+    }
+}
+
+fn print_aggregates(result: Vec<Run>, mode: EntropyMode) {
     print!("num_transactions\tnum_inputs_per_transaction");
     print!("\tduration_ms\tnum_outputs\tnon_derived_mappings");
     print!(
@@ -20,6 +136,7 @@ fn main() {
     );
     print!("\tinput_input_zeros\tinput_input_ones\tinput_input_average_other\tinput_input_average");
     print!("\toutput_output_zeros\toutput_output_ones\toutput_output_average_other\toutput_output_average");
+    print!("\tinterpretations\tentropy\tmin_anonymity_set\taverage_anonymity_set");
     println!();
     for run in result {
         let non_derived_partitions = filter_derived_partitions(&run.partition_tuples);
@@ -31,251 +148,160 @@ fn main() {
             run.out_coins.len(),
             non_derived_partitions.len()
         );
-        {
-            let (zeros, ones, average_other, average) = aggregated_in_out_probability(
-                &run.in_coins,
-                &run.out_coins,
-                &non_derived_partitions,
-            );
-            print!(
-                "\t{}\t{}\t{:.3}\t{:.3}",
-                zeros, ones, average_other, average
-            );
-        };
-        {
-            let (zeros, ones, average_other, average) =
-                aggregated_in_in_probability(&run.in_coins, &non_derived_partitions);
-            print!(
-                "\t{}\t{}\t{:.3}\t{:.3}",
-                zeros, ones, average_other, average
-            );
-        };
-        {
-            let (zeros, ones, average_other, average) =
-                aggregated_out_out_probability(&run.out_coins, &non_derived_partitions);
-            print!(
-                "\t{}\t{}\t{:.3}\t{:.3}",
-                zeros, ones, average_other, average
-            );
-        };
+        let probabilities =
+            LinkProbabilities::new(&run.in_coins, &run.out_coins, &non_derived_partitions);
+        print_aggregated(&probabilities.aggregated_in_out());
+        print_aggregated(&probabilities.aggregated_in_in());
+        print_aggregated(&probabilities.aggregated_out_out());
+        let metrics =
+            PrivacyMetrics::new(&run.in_coins, &run.out_coins, &run.partition_tuples, mode);
+        print!(
+            "\t{}\t{:.3}\t{}\t{:.3}",
+            metrics.interpretations,
+            metrics.entropy,
+            metrics.min_anonymity_set(),
+            metrics.average_anonymity_set()
+        );
         println!()
     }
 }
 
-fn average(v: &Vec<f64>) -> f64 {
-    match v.len() {
-        0 => 0f64,
-        _ => v.iter().sum::<f64>() / v.len() as f64,
-    }
+/// The values of each metric summarized by `--summary`, for one run.
+fn summary_metrics(run: &Run) -> Vec<(&'static str, f64)> {
+    let non_derived_partitions = filter_derived_partitions(&run.partition_tuples);
+    let probabilities =
+        LinkProbabilities::new(&run.in_coins, &run.out_coins, &non_derived_partitions);
+    let in_out = probabilities.aggregated_in_out();
+    let in_in = probabilities.aggregated_in_in();
+    let out_out = probabilities.aggregated_out_out();
+    // Without mappings the link metrics are unknown and left out of the summary.
+    let ones = |aggregated: Option<AggregatedProbabilities>| {
+        aggregated.map_or(f64::NAN, |aggregated| aggregated.ones as f64)
+    };
+    let average = |aggregated: Option<AggregatedProbabilities>| {
+        aggregated.map_or(f64::NAN, |aggregated| aggregated.average)
+    };
+    vec![
+        (
+            "duration_ms",
+            (run.duration_secs * 1_000) as f64 + run.duration_nano as f64 / 1_000_000f64,
+        ),
+        ("mappings", run.partition_tuples.len() as f64),
+        ("non_derived_mappings", non_derived_partitions.len() as f64),
+        ("input_output_ones", ones(in_out)),
+        ("input_output_average", average(in_out)),
+        ("input_input_ones", ones(in_in)),
+        ("input_input_average", average(in_in)),
+        ("output_output_ones", ones(out_out)),
+        ("output_output_average", average(out_out)),
+    ]
 }
 
-fn filter_derived_partitions(
-    partitions: &Vec<(Partition, Partition)>,
-) -> Vec<(Partition, Partition)> {
-    let max_index = partitions
-        .iter()
-        .map(|(in_p, _)| in_p.len())
-        .max()
-        .unwrap();
-    let mut sorted_partitions: Vec<Vec<&(Partition, Partition)>> = Vec::with_capacity(max_index);
-    for _ in 0..(max_index + 1) {
-        sorted_partitions.push(Vec::new());
-    }
-    for partition in partitions {
-        let size = partition.1.len();
-        sorted_partitions[size].push(partition);
-    }
-    let mut non_derived = Vec::new();
-    for i in 1..max_index {
-        let partitions = &sorted_partitions[i];
-        let plus_one_partitions = &sorted_partitions[i + 1];
-        'outer: for partition in partitions {
-            for plus_one_partition in plus_one_partitions {
-                if is_derived(partition, plus_one_partition) {
-                    continue 'outer;
-                }
-            }
-            non_derived.push(partition.clone().clone())
-        }
+/// Prints one line per configuration and metric, grouping runs by the number
+/// of transactions, their size, how they were shuffled and the shapes file
+/// their sizes were drawn from, if any.
+fn print_summary(result: Vec<Run>, resamples: usize) {
+    let mut groups: BTreeMap<(u64, u64, String, String), Vec<Vec<(&'static str, f64)>>> =
+        BTreeMap::new();
+    for run in result.iter() {
+        let shuffled = run
+            .shuffled
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string());
+        let shapes = run.shapes.clone().unwrap_or_else(|| "-".to_string());
+        groups
+            .entry((
+                run.num_transactions,
+                run.num_inputs_per_transaction,
+                shuffled,
+                shapes,
+            ))
+            .or_default()
+            .push(summary_metrics(run));
     }
-    for p in sorted_partitions[max_index].clone() {
-        non_derived.push(p.clone());
-    }
-    non_derived
-}
-
-fn is_derived(part: &(Partition, Partition), plus_part: &(Partition, Partition)) -> bool {
-    let in_partition = &part.1;
-    let mut in_partition_retained: Vec<Vec<u64>> = Vec::new();
-    let mut plus_in_partition = plus_part.1.clone();
-    assert!(in_partition.len() + 1 == plus_in_partition.len());
-    for in_set in in_partition {
-        let mtch = {
-            plus_in_partition
-                .iter()
-                .position(|set| in_set.iter().sum::<u64>() == set.iter().sum::<u64>())
-        };
-        match mtch {
-            Some(i) => {
-                plus_in_partition.remove(i);
-            }
-            None => in_partition_retained.push(in_set.clone()),
+    print!("num_transactions\tnum_inputs_per_transaction\tshuffled\tshapes\tmetric\truns");
+    print!("\tmean\tstandard_deviation\tmin\tp5\tp25\tmedian\tp75\tp95\tmax");
+    println!("\tmean_ci_lower\tmean_ci_upper");
+    let mut rng = thread_rng();
+    for ((num_transactions, num_inputs_per_transaction, shuffled, shapes), runs) in groups {
+        for (index, (metric, _)) in runs[0].iter().enumerate() {
+            let values: Vec<f64> = runs.iter().map(|metrics| metrics[index].1).collect();
+            let summary = Summary::new(&values, resamples, &mut rng);
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}",
+                num_transactions,
+                num_inputs_per_transaction,
+                shuffled,
+                shapes,
+                metric,
+                summary.samples,
+                summary.mean,
+                summary.standard_deviation,
+                summary.min,
+                summary.p5,
+                summary.p25,
+                summary.median,
+                summary.p75,
+                summary.p95,
+                summary.max,
+                summary.mean_lower,
+                summary.mean_upper
+            );
         }
     }
-    if in_partition_retained.len() == 1 && plus_in_partition.len() == 2 {
-        return true;
-    }
-    false
 }
 
-#[test]
-fn test_is_derived() {
-    assert!(is_derived(
-        &(
-            vec![vec![1, 2, 3], vec![3, 4]],
-            vec![vec![1, 2, 3], vec![3, 4]]
+fn print_aggregated(aggregated: &Option<AggregatedProbabilities>) {
+    match aggregated {
+        Some(aggregated) => print!(
+            "\t{}\t{}\t{:.3}\t{:.3}",
+            aggregated.zeros, aggregated.ones, aggregated.average_other, aggregated.average
         ),
-        &(
-            vec![vec![1, 2], vec![3], vec![3, 4]],
-            vec![vec![1, 2], vec![3], vec![3, 4]]
-        )
-    ));
-    assert!(is_derived(
-        &(
-            vec![vec![2, 2, 3], vec![3, 4]],
-            vec![vec![2, 2, 3], vec![3, 4]]
-        ),
-        &(
-            vec![vec![3, 2], vec![2], vec![3, 4]],
-            vec![vec![3, 2], vec![2], vec![3, 4]]
-        )
-    ))
-}
-
-fn aggregate_probabilities(probabilities: &Vec<f64>) -> (f64, f64, f64, f64) {
-    let zeros = probabilities.iter().filter(|&&p| p == 0f64).count() as f64;
-    let ones = probabilities.iter().filter(|&&p| p == 1f64).count() as f64;
-    let other: Vec<f64> = probabilities
-        .iter()
-        .filter(|&&p| p > 0f64 && p < 1f64).copied()
-        .collect();
-    let average_other = average(&other);
-    let average = average(probabilities);
-    (zeros, ones, average_other, average)
-}
-
-fn in_out_probability(
-    in_coin: &u64,
-    out_coin: &u64,
-    partition_tuples: &Vec<(Partition, Partition)>,
-) -> f64 {
-    partition_tuples
-        .iter()
-        .filter(|&(in_partition, out_partition)| {
-            let in_set = match in_partition
-                .iter()
-                .find(|set| set.iter().any(|coin| coin == in_coin))
-            {
-                Some(set) => set,
-                None => panic!("Did not find in coin in partition"),
-            };
-            let out_set = match out_partition
-                .iter()
-                .find(|set| set.iter().any(|coin| coin == out_coin))
-            {
-                Some(set) => set,
-                None => panic!("Did not find out coin in partition"),
-            };
-            in_set.iter().sum::<u64>() == out_set.iter().sum::<u64>()
-        })
-        .count() as f64
-        / partition_tuples.len() as f64
-}
-
-fn aggregated_in_out_probability(
-    in_coins: &Vec<u64>,
-    out_coins: &Vec<u64>,
-    partition_tuples: &Vec<(Partition, Partition)>,
-) -> (f64, f64, f64, f64) {
-    let probabilities: Vec<f64> = in_coins
-        .iter()
-        .flat_map(|in_coin| {
-            out_coins
-                .iter()
-                .map(move |out_coin| in_out_probability(in_coin, out_coin, partition_tuples))
-        })
-        .collect();
-    aggregate_probabilities(&probabilities)
-}
-
-fn in_in_probability(
-    first_in_coin: &u64,
-    second_in_coin: &u64,
-    partition_tuples: &Vec<(Partition, Partition)>,
-) -> f64 {
-    partition_tuples
-        .iter()
-        .filter(|&(in_partition, _)| {
-            in_partition
-                .iter()
-                .find(|set| {
-                    set.iter().any(|coin| coin == first_in_coin)
-                        && set.iter().any(|coin| coin == second_in_coin)
-                })
-                .is_some()
-        })
-        .count() as f64
-        / partition_tuples.len() as f64
-}
-
-fn aggregated_in_in_probability(
-    in_coins: &Vec<u64>,
-    partition_tuples: &Vec<(Partition, Partition)>,
-) -> (f64, f64, f64, f64) {
-    let probabilities: Vec<f64> = in_coins
-        .iter()
-        .enumerate()
-        .flat_map(|(i, first_in_coin)| {
-            in_coins.iter().skip(i + 1).map(move |second_in_coin| {
-                in_in_probability(first_in_coin, second_in_coin, partition_tuples)
-            })
-        })
-        .collect();
-    aggregate_probabilities(&probabilities)
+        None => print!("\tNA\tNA\tNA\tNA"),
+    }
 }
 
-fn out_out_probability(
-    first_out_coin: &u64,
-    second_out_coin: &u64,
-    partition_tuples: &Vec<(Partition, Partition)>,
-) -> f64 {
-    partition_tuples
-        .iter()
-        .filter(|&(_, out_partition)| {
-            out_partition
-                .iter()
-                .find(|set| {
-                    set.iter().any(|coin| coin == first_out_coin)
-                        && set.iter().any(|coin| coin == second_out_coin)
-                })
-                .is_some()
-        })
-        .count() as f64
-        / partition_tuples.len() as f64
+fn print_scores(result: Vec<Run>) {
+    println!("num_transactions\tnum_inputs_per_transaction\tnon_derived_mappings\tfound\trank\tprobability\tcertain_input_links\tcertain_input_output_links\tparticipants\tidentified_participants");
+    let optional = |value: Option<f64>| match value {
+        Some(value) => format!("{:.3}", value),
+        None => "NA".to_string(),
+    };
+    for run in result {
+        let score = GroundTruthScore::new(&run);
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{:.3}\t{}\t{}\t{}\t{}",
+            run.num_transactions,
+            run.num_inputs_per_transaction,
+            score.mappings,
+            score.found,
+            score.rank.map_or("NA".to_string(), |rank| rank.to_string()),
+            score.probability,
+            optional(score.certain_input_links),
+            optional(score.certain_input_output_links),
+            score.participants,
+            score.identified_participants
+        );
+    }
 }
 
-fn aggregated_out_out_probability(
-    out_coins: &Vec<u64>,
-    partition_tuples: &Vec<(Partition, Partition)>,
-) -> (f64, f64, f64, f64) {
-    let probabilities: Vec<f64> = out_coins
-        .iter()
-        .enumerate()
-        .flat_map(|(i, first_out_coin)| {
-            out_coins.iter().skip(i + 1).map(move |second_out_coin| {
-                out_out_probability(first_out_coin, second_out_coin, partition_tuples)
-            })
-        })
-        .collect();
-    aggregate_probabilities(&probabilities)
+fn print_score_summary(result: Vec<Run>) {
+    let scores: Vec<GroundTruthScore> = result.iter().map(GroundTruthScore::new).collect();
+    let summary = ScoreSummary::new(&scores);
+    println!("metric\tsamples\tmean\tci_lower\tci_upper");
+    let print_estimate = |metric: &str, estimate: &Estimate| {
+        println!(
+            "{}\t{}\t{:.3}\t{:.3}\t{:.3}",
+            metric, estimate.samples, estimate.mean, estimate.lower, estimate.upper
+        )
+    };
+    print_estimate("found", &summary.found);
+    print_estimate("rank", &summary.rank);
+    print_estimate("probability", &summary.probability);
+    print_estimate("certain_input_links", &summary.certain_input_links);
+    print_estimate(
+        "certain_input_output_links",
+        &summary.certain_input_output_links,
+    );
+    print_estimate("identified_participants", &summary.identified_participants);
 }
@@ -2,105 +2,300 @@ extern crate rand;
 use std::fs::File;
 use std::io::Write;
 use std::process::exit;
-use std::time::Instant;
 
-extern crate rmp_serde;
 extern crate serde;
 extern crate serde_json;
 #[allow(unused_imports)]
 #[macro_use]
 extern crate serde_derive;
-extern crate rmp_serde as rmps;
 
 extern crate rayon;
 use rayon::prelude::*;
 
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
 #[macro_use(value_t)]
 extern crate clap;
+extern crate ctrlc;
 use clap::{App, Arg, ArgMatches, SubCommand};
 
 extern crate coinjoin_analyzer;
 use coinjoin_analyzer::{
-    Distribution, Partition, PartitionsSubsetSumsFilter, Run, SubsetSumsFilter,
-    SumFilteredPartitionIterator,
+    expand_block_files, filter_derived_partitions, hash_from_hex, hash_to_hex, locate_main_chain,
+    parse_amount, read_shapes, recover_ndjson, validate_transaction, Analysis, Analyzer,
+    AnalyzerOptions, BlockLocation, CancellationToken, ChainIterator, CoinSampler, EntropyMode,
+    LinkProbabilities, MessagePackWriter, NdjsonWriter, Pairing, Partition, PrevoutValues,
+    PrivacyMetrics, ResultHeader, Run, SamplerSpec, SearchLimits, SearchStatus, Set, ShapeSampler,
+    Transaction, ValidatedTransaction, LINK_CSV_HEADER,
 };
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fs::OpenOptions;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter, Read};
 use std::path::Path;
-
-use serde::de::DeserializeOwned;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
 fn main() {
     let matches = get_app().get_matches();
     match matches.subcommand() {
         ("auto", Some(options)) => auto(options),
         ("analyze", Some(options)) => analyze(options),
+        ("sweep", Some(options)) => sweep(options),
+        ("chain", Some(options)) => chain(options),
         _ => {
             let _ = get_app().print_help();
         }
     }
 }
 
+/// Exit code of `cja analyze` if the inputs, outputs or flag values cannot be
+/// parsed or the outputs exceed the inputs.
+const EXIT_INVALID_INPUT: i32 = 2;
+/// Exit code of `cja analyze` if the transaction has no mapping.
+const EXIT_NO_MAPPINGS: i32 = 3;
+/// Exit code of `cja analyze` if the search was stopped by its budget.
+const EXIT_TRUNCATED: i32 = 4;
+
+/// Prints the error about an invalid flag value of `cja analyze` and exits with
+/// `EXIT_INVALID_INPUT` instead of clap's status 1.
+fn invalid_input(error: clap::Error) -> ! {
+    eprintln!("{}", error.message);
+    exit(EXIT_INVALID_INPUT)
+}
+
+/// The result of `cja analyze --format json`.
+#[derive(Serialize)]
+struct AnalyzeReport {
+    /// The analyzed coins, without the coins of zero value and with the fee
+    /// as last output if it is analyzed as one.
+    inputs: Vec<u64>,
+    outputs: Vec<u64>,
+    fee: u64,
+    zero_inputs: Vec<usize>,
+    zero_outputs: Vec<usize>,
+    mappings: Vec<MappingReport>,
+    mapping_count: usize,
+    non_derived_mappings: usize,
+    metrics: PrivacyMetrics,
+    search_status: SearchStatus,
+    complete: bool,
+    duration_ms: f64,
+}
+
+#[derive(Serialize)]
+struct MappingReport {
+    input_sets: Partition,
+    output_sets: Partition,
+    pairing: Pairing,
+}
+
 fn analyze(options: &ArgMatches) {
-    let inputs: Vec<u64> = value_t!(options.value_of("inputs"), String)
-        .unwrap_or_else(|e| e.exit())
-        .split(',')
-        .map(|i| {
-            i.parse::<u64>().unwrap_or_else(|e| {
-                println!("Invalid input value {}: {}", i, e);
-                exit(1)
-            })
-        })
-        .collect();
-    let outputs: Vec<u64> = value_t!(options.value_of("outputs"), String)
-        .unwrap_or_else(|e| e.exit())
+    let parallelism =
+        value_t!(options.value_of("parallelism"), usize).unwrap_or_else(|e| invalid_input(e));
+    let _ = rayon::initialize(rayon::Configuration::new().set_num_threads(parallelism));
+    if options.is_present("matrix") && options.occurrences_of("format") > 0 {
+        eprintln!("--matrix prints the link probabilities instead of the mappings, so --format can't be used with it");
+        exit(EXIT_INVALID_INPUT)
+    }
+    let transaction = match validate_transaction(
+        &parse_amounts(options, "inputs"),
+        &parse_amounts(options, "outputs"),
+    ) {
+        Ok(transaction) => transaction,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(EXIT_INVALID_INPUT)
+        }
+    };
+    if !transaction.zero_inputs.is_empty() {
+        eprintln!(
+            "Ignoring inputs without value at {:?}",
+            transaction.zero_inputs
+        );
+    }
+    if !transaction.zero_outputs.is_empty() {
+        eprintln!(
+            "Ignoring outputs without value at {:?}",
+            transaction.zero_outputs
+        );
+    }
+    let inputs = transaction.inputs.clone();
+    let mut outputs = transaction.outputs.clone();
+    if transaction.fee > 0 {
+        if options.is_present("fee-as-output") {
+            outputs.push(transaction.fee);
+        } else {
+            eprintln!(
+                "The inputs exceed the outputs by a fee of {} satoshi, so no mapping can \
+                 balance them. Use --fee-as-output to analyze the fee as an output.",
+                transaction.fee
+            );
+        }
+    }
+    let analyzer = Analyzer::new(AnalyzerOptions {
+        limits: search_limits(options).unwrap_or_else(|e| invalid_input(e)),
+        parallel: true,
+        cancellation: cancel_on_interrupt(),
+        ..AnalyzerOptions::default()
+    });
+    let analysis = analyzer.analyze(&inputs, &outputs);
+    let mode = match options.value_of("entropy") {
+        Some("boltzmann") => EntropyMode::Boltzmann,
+        _ => EntropyMode::NonDerived,
+    };
+    if let Some(format) = options.value_of("matrix") {
+        // Like calculate_probabilities, only count the non-derived mappings.
+        let non_derived_partitions = filter_derived_partitions(&analysis.partition_tuples);
+        let links = LinkProbabilities::new(&inputs, &outputs, &non_derived_partitions);
+        if format == "json" {
+            println!(
+                "{}",
+                serde_json::to_string(&links).expect("Unable to serialize link probabilities")
+            );
+        } else {
+            let stdout = std::io::stdout();
+            let mut out = stdout.lock();
+            let _ = writeln!(out, "{}", LINK_CSV_HEADER);
+            let _ = links.write_csv(&mut out, 0);
+        }
+    } else {
+        match options.value_of("format") {
+            Some("json") => print_analysis_json(&transaction, &inputs, &outputs, &analysis, mode),
+            Some("tsv") => print_analysis_tsv(&analysis),
+            _ => print_analysis_text(&transaction, &inputs, &outputs, &analysis, mode),
+        }
+    }
+    if !analysis.search_status.is_complete() {
+        exit(EXIT_TRUNCATED)
+    }
+    if analysis.partition_tuples.is_empty() {
+        exit(EXIT_NO_MAPPINGS)
+    }
+}
+
+/// Parses the comma separated amounts of the argument `name`, in satoshis or
+/// in bitcoin with a decimal point, exiting with `EXIT_INVALID_INPUT` on
+/// invalid values.
+fn parse_amounts(options: &ArgMatches, name: &str) -> Vec<u64> {
+    let values = match options.value_of(name) {
+        Some(values) => values,
+        None => {
+            eprintln!("No {} given", name);
+            exit(EXIT_INVALID_INPUT)
+        }
+    };
+    values
         .split(',')
-        .map(|o| {
-            o.parse::<u64>().unwrap_or_else(|e| {
-                println!("Invalid output value {}: {}", o, e);
-                exit(1)
+        .map(|value| {
+            parse_amount(value).unwrap_or_else(|e| {
+                eprintln!("Invalid value {} in {}: {}", value, name, e);
+                exit(EXIT_INVALID_INPUT)
             })
         })
-        .collect();
-    let in_partitions: Vec<Partition> = {
-        SumFilteredPartitionIterator::new(inputs.clone(), &SubsetSumsFilter::new(&outputs))
-            .collect()
-    };
-    let out_partitions: Vec<Partition> = {
-        SumFilteredPartitionIterator::new(
-            outputs.clone(),
-            &PartitionsSubsetSumsFilter::new(&in_partitions),
-        )
         .collect()
-    };
-    let mut partition_tuples: Vec<(Partition, Partition)> = Vec::new();
-    for in_partition in in_partitions {
-        for out_partition in out_partitions.clone() {
-            if partitions_match(&in_partition, &out_partition) {
-                partition_tuples.push((in_partition.clone(), out_partition.clone()));
-            }
-        }
+}
+
+fn print_analysis_text(
+    transaction: &ValidatedTransaction,
+    inputs: &Set,
+    outputs: &Set,
+    analysis: &Analysis,
+    mode: EntropyMode,
+) {
+    if transaction.fee > 0 {
+        println!("Fee: {}", transaction.fee);
     }
-    for (input_sets, output_sets) in partition_tuples.iter() {
+    for (input_sets, output_sets) in analysis.partition_tuples.iter() {
         println!(
             "Input sets: {:?} Output sets: {:?}",
             input_sets, output_sets
         );
     }
+    let metrics = PrivacyMetrics::new(inputs, outputs, &analysis.partition_tuples, mode);
+    println!(
+        "Interpretations: {} Entropy: {:.3} bits",
+        metrics.interpretations, metrics.entropy
+    );
+    println!("Output anonymity sets: {:?}", metrics.output_anonymity_sets);
+    if !analysis.search_status.is_complete() {
+        eprintln!("Search incomplete: {:?}", analysis.search_status);
+    }
+}
+
+fn print_analysis_json(
+    transaction: &ValidatedTransaction,
+    inputs: &Set,
+    outputs: &Set,
+    analysis: &Analysis,
+    mode: EntropyMode,
+) {
+    let report = AnalyzeReport {
+        inputs: inputs.clone(),
+        outputs: outputs.clone(),
+        fee: transaction.fee,
+        zero_inputs: transaction.zero_inputs.clone(),
+        zero_outputs: transaction.zero_outputs.clone(),
+        mappings: analysis
+            .partition_tuples
+            .iter()
+            .zip(analysis.pairings.iter())
+            .map(|((input_sets, output_sets), pairing)| MappingReport {
+                input_sets: input_sets.clone(),
+                output_sets: output_sets.clone(),
+                pairing: pairing.clone(),
+            })
+            .collect(),
+        mapping_count: analysis.partition_tuples.len(),
+        non_derived_mappings: filter_derived_partitions(&analysis.partition_tuples).len(),
+        metrics: PrivacyMetrics::new(inputs, outputs, &analysis.partition_tuples, mode),
+        search_status: analysis.search_status,
+        complete: analysis.search_status.is_complete(),
+        duration_ms: analysis.duration.as_secs() as f64 * 1_000f64
+            + analysis.duration.subsec_nanos() as f64 / 1_000_000f64,
+    };
+    println!(
+        "{}",
+        serde_json::to_string(&report).expect("Unable to serialize analysis")
+    );
+}
+
+/// Prints one line per mapping, the parts of a partition are separated by `;`
+/// and the coins of a part by `,`.
+fn print_analysis_tsv(analysis: &Analysis) {
+    let format_partition = |partition: &Partition| {
+        partition
+            .iter()
+            .map(|set| {
+                set.iter()
+                    .map(|coin| coin.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .collect::<Vec<String>>()
+            .join(";")
+    };
+    println!("mapping\tparts\tinput_sets\toutput_sets");
+    for (index, (input_sets, output_sets)) in analysis.partition_tuples.iter().enumerate() {
+        println!(
+            "{}\t{}\t{}\t{}",
+            index,
+            input_sets.len(),
+            format_partition(input_sets),
+            format_partition(output_sets)
+        );
+    }
 }
 
 fn auto(options: &ArgMatches) {
     let parallelism = value_t!(options.value_of("parallelism"), usize).unwrap_or_else(|e| e.exit());
     let _ = rayon::initialize(rayon::Configuration::new().set_num_threads(parallelism));
-    let distribution_file_name = match options.value_of("distribution") {
-        Some(string) => string,
-        None => return print!("No distribution file given!"),
-    };
-    let distribution = match read_distribution(distribution_file_name) {
-        Ok(dist) => dist,
-        Err(err) => return println!("Error while reading distribution: {}", err),
+    let (spec, sampler) = match read_sampler(options) {
+        Ok(sampler) => sampler,
+        Err(err) => return println!("Error while creating the coin sampler: {}", err),
     };
     let transactions = value_t!(options.value_of("transactions"), u64).unwrap_or_else(|e| e.exit());
     let transaction_size = value_t!(options.value_of("size"), u64).unwrap_or_else(|e| e.exit());
@@ -113,24 +308,660 @@ fn auto(options: &ArgMatches) {
     {
         return print!("Passed invalid value for shuffled parameter");
     }
+    let shapes = match options.value_of("shapes") {
+        Some(_) if shuffled != "none" => {
+            return println!("Shapes can only be drawn for transactions without shuffling")
+        }
+        Some(file_name) => match read_shape_sampler(options, file_name) {
+            Ok(shapes) => Some(shapes),
+            Err(err) => return println!("Error while reading the shapes: {}", err),
+        },
+        None => None,
+    };
+    let format = result_format(options);
     let result_file_name = match options.value_of("output") {
         Some(string) => string.to_string(),
         None => format!(
-            "result-{}-t-{}-s-{}-r-{}.json",
-            shuffled, transactions, transaction_size, runs
+            "result-{}-t-{}-s-{}-r-{}.{}",
+            shuffled,
+            transactions,
+            transaction_size,
+            runs,
+            format.extension()
         ),
     };
+    let mut parameters = BTreeMap::new();
+    parameters.insert("transactions".to_string(), transactions.to_string());
+    parameters.insert("size".to_string(), transaction_size.to_string());
+    parameters.insert("shuffled".to_string(), shuffled.clone());
+    parameters.insert("runs".to_string(), runs.to_string());
+    parameters.insert(
+        "sampler".to_string(),
+        serde_json::to_string(&spec).expect("Unable to serialize sampler"),
+    );
+    if let Some(file_name) = options.value_of("shapes") {
+        parameters.insert("shapes".to_string(), file_name.to_string());
+        for name in &["max-inputs", "max-outputs"] {
+            parameters.insert(
+                name.to_string(),
+                options.value_of(name).unwrap().to_string(),
+            );
+        }
+    }
+
+    let analyzer = Analyzer::new(AnalyzerOptions {
+        limits: search_limits(options).unwrap_or_else(|e| e.exit()),
+        cancellation: cancel_on_interrupt(),
+        ..AnalyzerOptions::default()
+    });
+
+    let mut jobs = vec![(transactions, transaction_size, &shuffled); runs];
+    if options.is_present("resume") {
+        let recovered = match recover_ndjson(Path::new(&result_file_name)) {
+            Ok(recovered) => recovered,
+            Err(err) => return println!("Error while reading {}: {}", result_file_name, err),
+        };
+        let other = recovered.runs.iter().find(|run| {
+            run.num_transactions != transactions
+                || run.num_inputs_per_transaction != transaction_size
+                || run.shuffled.as_ref() != Some(&shuffled)
+                || run.shapes.as_ref().map(|s| s.as_str()) != options.value_of("shapes")
+        });
+        if let Some(run) = other {
+            return println!(
+                "{} holds runs with -t {} -s {} -S {}{}, not the given parameters",
+                result_file_name,
+                run.num_transactions,
+                run.num_inputs_per_transaction,
+                run.shuffled.as_ref().map_or("unknown", |s| s.as_str()),
+                run.shapes
+                    .as_ref()
+                    .map_or(String::new(), |s| format!(" --shapes {}", s))
+            );
+        }
+        let completed = recovered.runs.len();
+        if completed >= runs {
+            return println!("All {} runs are already in {}", runs, result_file_name);
+        }
+        // Only now that the runs are known to match, the partially written
+        // last run is removed before appending.
+        if let Err(err) = recovered.truncate() {
+            return println!("Error while truncating {}: {}", result_file_name, err);
+        }
+        jobs.truncate(runs - completed);
+    }
+    let header = ResultHeader::new("cja auto", parameters);
+    generate(
+        sampler.as_ref(),
+        options.value_of("shapes").zip(shapes.as_ref()),
+        &analyzer,
+        jobs,
+        format,
+        &result_file_name,
+        &header,
+        options.is_present("resume"),
+    );
+}
+
+/// Runs `cja auto` for every combination of the given numbers of transactions,
+/// transaction sizes and shufflings. All runs share the distribution and the
+/// thread pool and are written to one result file, each `Run` records its
+/// parameters.
+fn sweep(options: &ArgMatches) {
+    let parallelism = value_t!(options.value_of("parallelism"), usize).unwrap_or_else(|e| e.exit());
+    let _ = rayon::initialize(rayon::Configuration::new().set_num_threads(parallelism));
+    let transactions = parse_values(options.value_of("transactions").unwrap_or_default())
+        .unwrap_or_else(|e| {
+            println!("Invalid number of transactions: {}", e);
+            exit(1)
+        });
+    let sizes = parse_values(options.value_of("size").unwrap_or_default()).unwrap_or_else(|e| {
+        println!("Invalid transaction size: {}", e);
+        exit(1)
+    });
+    let shufflings: Vec<String> = options
+        .values_of("shuffled")
+        .map(|values| values.map(|v| v.to_string()).collect())
+        .unwrap_or_default();
+    let runs = value_t!(options.value_of("runs"), usize).unwrap_or_else(|e| e.exit());
+    let (spec, sampler) = match read_sampler(options) {
+        Ok(sampler) => sampler,
+        Err(err) => return println!("Error while creating the coin sampler: {}", err),
+    };
+    let format = result_format(options);
+    let result_file_name = match options.value_of("output") {
+        Some(string) => string.to_string(),
+        None => format!("result-sweep-r-{}.{}", runs, format.extension()),
+    };
+    let mut parameters = BTreeMap::new();
+    for name in &["transactions", "size", "runs"] {
+        parameters.insert(
+            name.to_string(),
+            options.value_of(name).unwrap_or_default().to_string(),
+        );
+    }
+    parameters.insert("shuffled".to_string(), shufflings.join(","));
+    parameters.insert(
+        "sampler".to_string(),
+        serde_json::to_string(&spec).expect("Unable to serialize sampler"),
+    );
+
+    let analyzer = Analyzer::new(AnalyzerOptions {
+        limits: search_limits(options).unwrap_or_else(|e| e.exit()),
+        cancellation: cancel_on_interrupt(),
+        ..AnalyzerOptions::default()
+    });
+
+    let mut cells: Vec<(u64, u64, &String)> = Vec::new();
+    for shuffled in shufflings.iter() {
+        for &num_transactions in transactions.iter() {
+            for &transaction_size in sizes.iter() {
+                for _ in 0..runs {
+                    cells.push((num_transactions, transaction_size, shuffled));
+                }
+            }
+        }
+    }
+    let header = ResultHeader::new("cja sweep", parameters);
+    generate(
+        sampler.as_ref(),
+        None,
+        &analyzer,
+        cells,
+        format,
+        &result_file_name,
+        &header,
+        false,
+    );
+}
+
+/// A line of the candidates of `cja chain` in JSON, e.g. written by
+/// `detect_coinjoins`. Other fields are ignored.
+#[derive(Deserialize)]
+struct ChainCandidate {
+    txid: String,
+    #[serde(default)]
+    height: Option<u64>,
+}
+
+/// An on-chain transaction to analyze, with the fee as last output.
+struct ChainJob {
+    txid: String,
+    height: u64,
+    inputs: Vec<u64>,
+    outputs: Vec<u64>,
+}
+
+/// Analyzes on-chain transactions, e.g. the candidates of `detect_coinjoins`.
+/// The block files are read twice, first to find the transactions and then
+/// to collect only the values of the coins they spend.
+fn chain(options: &ArgMatches) {
+    let parallelism = value_t!(options.value_of("parallelism"), usize).unwrap_or_else(|e| e.exit());
+    let _ = rayon::initialize(rayon::Configuration::new().set_num_threads(parallelism));
+    let candidates_file = options.value_of("candidates").unwrap();
+    let candidates = match read_candidates(candidates_file) {
+        Ok(candidates) => candidates,
+        Err(err) => return println!("Error while reading {}: {}", candidates_file, err),
+    };
+    let blocks: Vec<&str> = options.values_of("blocks").unwrap().collect();
+    let jobs = match resolve_candidates(&blocks, &candidates) {
+        Ok(jobs) => jobs,
+        Err(err) => return println!("Error while reading the block files: {}", err),
+    };
+    let format = result_format(options);
+    let result_file_name = match options.value_of("output") {
+        Some(string) => string.to_string(),
+        None => format!("result-chain.{}", format.extension()),
+    };
+    let mut parameters = BTreeMap::new();
+    parameters.insert("candidates".to_string(), candidates_file.to_string());
+    parameters.insert("blocks".to_string(), blocks.join(","));
+
+    let analyzer = Analyzer::new(AnalyzerOptions {
+        limits: search_limits(options).unwrap_or_else(|e| e.exit()),
+        cancellation: cancel_on_interrupt(),
+        ..AnalyzerOptions::default()
+    });
+    let header = ResultHeader::new("cja chain", parameters);
+    write_runs(
+        jobs,
+        |job| chain_run(&analyzer, job),
+        format,
+        &result_file_name,
+        &header,
+        false,
+    );
+}
+
+/// Reads one candidate per line, either as JSON with a `txid` and an optional
+/// `height` or as a txid optionally followed by the height. `-` reads the
+/// standard input.
+fn read_candidates(file_name: &str) -> Result<Vec<([u8; 32], Option<u64>)>, Box<dyn Error>> {
+    let mut input = String::new();
+    if file_name == "-" {
+        std::io::stdin().read_to_string(&mut input)?;
+    } else {
+        File::open(file_name)?.read_to_string(&mut input)?;
+    }
+    let mut candidates = Vec::new();
+    let mut seen = HashSet::new();
+    for line in input.lines().map(|line| line.trim()) {
+        let (txid, height) = if line.is_empty() {
+            continue;
+        } else if line.starts_with('{') {
+            let candidate: ChainCandidate = serde_json::from_str(line)?;
+            (candidate.txid, candidate.height)
+        } else {
+            let mut fields = line.split_whitespace();
+            let txid = fields.next().unwrap_or_default().to_string();
+            let height = match fields.next() {
+                Some(height) => Some(height.parse::<u64>()?),
+                None => None,
+            };
+            (txid, height)
+        };
+        let hash = hash_from_hex(&txid).ok_or_else(|| format!("Invalid txid {}", txid))?;
+        if seen.insert(hash) {
+            candidates.push((hash, height));
+        }
+    }
+    Ok(candidates)
+}
+
+/// Finds the candidates in the main chain of the block files, only in the
+/// blocks of their heights if all are known, and resolves their input values.
+/// Candidates that are not found or whose spent coins are not found are
+/// skipped with a warning.
+fn resolve_candidates(
+    blocks: &[&str],
+    candidates: &[([u8; 32], Option<u64>)],
+) -> Result<Vec<ChainJob>, Box<dyn Error>> {
+    let files = expand_block_files(blocks)?;
+    let chain: Vec<(u64, usize, BlockLocation)> = locate_main_chain(&files, || {})?
+        .into_iter()
+        .enumerate()
+        .map(|(height, (file, location))| (height as u64, file, location))
+        .collect();
+    let wanted: HashMap<[u8; 32], Option<u64>> = candidates.iter().cloned().collect();
+    let heights: Option<BTreeSet<u64>> = candidates.iter().map(|&(_, height)| height).collect();
+    let candidate_blocks = chain
+        .iter()
+        .filter(|&&(height, _, _)| heights.as_ref().map_or(true, |h| h.contains(&height)))
+        .cloned()
+        .collect();
+    let mut found: Vec<(u64, Transaction)> = Vec::new();
+    for block in ChainIterator::new(files.clone(), candidate_blocks) {
+        let (height, block) = block?;
+        for transaction in block.transactions {
+            if let Some(&expected) = wanted.get(&transaction.txid) {
+                if expected.map_or(true, |expected| expected == height) {
+                    found.push((height, transaction));
+                }
+            }
+        }
+        if found.len() == wanted.len() {
+            break;
+        }
+    }
+    println!("Found {} of {} candidates", found.len(), wanted.len());
+    let found_txids: HashSet<[u8; 32]> = found
+        .iter()
+        .map(|(_, transaction)| transaction.txid)
+        .collect();
+    for &(txid, _) in candidates.iter() {
+        if !found_txids.contains(&txid) {
+            eprintln!("Warning: {} is not in the main chain", hash_to_hex(&txid));
+        }
+    }
 
-    let mut result: Vec<Run> = Vec::new();
-    (0..runs)
-        .into_par_iter()
-        .weight_max()
-        .map(|_| run(&distribution, transactions, transaction_size, &shuffled))
-        .collect_into(&mut result);
+    let mut prevouts = PrevoutValues::new(found.iter().map(|(_, transaction)| transaction));
+    // Coins are spent after they are created, at the latest in the same block.
+    let last = found.iter().map(|&(height, _)| height).max().unwrap_or(0);
+    let earlier_blocks = chain
+        .into_iter()
+        .take_while(|&(height, _, _)| height <= last)
+        .collect();
+    if !found.is_empty() {
+        for block in ChainIterator::new(files, earlier_blocks) {
+            let (_, block) = block?;
+            for transaction in block.transactions.iter() {
+                prevouts.add_transaction(transaction);
+            }
+            if prevouts.is_complete() {
+                break;
+            }
+        }
+    }
 
-    let mut file = File::create(result_file_name).unwrap();
-    let json_string = serde_json::to_string(&result).unwrap();
-    let _ = file.write(json_string.as_bytes());
+    let mut jobs = Vec::new();
+    for (height, transaction) in found {
+        let txid = hash_to_hex(&transaction.txid);
+        let inputs = match prevouts.input_values(&transaction) {
+            Some(inputs) => inputs,
+            None => {
+                eprintln!(
+                    "Warning: some coins spent by {} are not in the block files",
+                    txid
+                );
+                continue;
+            }
+        };
+        let outputs: Vec<u64> = transaction
+            .outputs
+            .iter()
+            .map(|output| output.value.max(0) as u64)
+            .collect();
+        // Every real transaction pays a fee, which is analyzed as an output
+        // like with `cja analyze --fee-as-output`.
+        match validate_transaction(&inputs, &outputs) {
+            Ok(validated) => {
+                let mut outputs = validated.outputs;
+                if validated.fee > 0 {
+                    outputs.push(validated.fee);
+                }
+                jobs.push(ChainJob {
+                    txid,
+                    height,
+                    inputs: validated.inputs,
+                    outputs,
+                })
+            }
+            Err(err) => eprintln!("Warning: skipping {}: {}", txid, err),
+        }
+    }
+    Ok(jobs)
+}
+
+fn chain_run(analyzer: &Analyzer, job: ChainJob) -> Run {
+    let analysis = analyzer.analyze(&job.inputs, &job.outputs);
+    Run {
+        num_transactions: 0,
+        num_inputs_per_transaction: 0,
+        shuffled: None,
+        shapes: None,
+        original_transactions: Vec::new(),
+        in_coins: job.inputs,
+        out_coins: job.outputs,
+        partition_tuples: analysis.partition_tuples,
+        pairings: analysis.pairings,
+        duration_secs: analysis.duration.as_secs(),
+        duration_nano: analysis.duration.subsec_nanos(),
+        search_status: analysis.search_status,
+        txid: Some(job.txid),
+        height: Some(job.height),
+    }
+}
+
+/// Parses a comma separated list of numbers and inclusive ranges like
+/// `2,4-6`.
+fn parse_values(spec: &str) -> Result<Vec<u64>, String> {
+    let mut values = Vec::new();
+    for item in spec.split(',').map(|item| item.trim()) {
+        let parse = |value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|e| format!("{} in {}", e, item))
+        };
+        match item.find('-') {
+            Some(dash) => {
+                let (first, last) = (parse(&item[..dash])?, parse(&item[dash + 1..])?);
+                if first > last {
+                    return Err(format!("empty range {}", item));
+                }
+                values.extend(first..=last);
+            }
+            None => values.push(parse(item)?),
+        }
+    }
+    Ok(values)
+}
+
+/// How `cja auto` and `cja sweep` write their runs.
+#[derive(Clone, Copy)]
+enum ResultFormat {
+    /// One JSON array, written once all runs are finished.
+    Json,
+    /// One line of JSON per run, written as soon as it is finished. The
+    /// default, so that a crash only loses the runs still being analyzed.
+    Ndjson,
+    /// The MessagePack result format, also written as runs finish.
+    MessagePack { zstd: bool },
+}
+
+impl ResultFormat {
+    fn extension(&self) -> &'static str {
+        match *self {
+            ResultFormat::Json => "json",
+            ResultFormat::Ndjson => "ndjson",
+            ResultFormat::MessagePack { zstd: false } => "msgpack",
+            ResultFormat::MessagePack { zstd: true } => "msgpack.zst",
+        }
+    }
+}
+
+fn result_format(options: &ArgMatches) -> ResultFormat {
+    if options.is_present("zstd") && !cfg!(feature = "zstd") {
+        println!("cja was built without the zstd feature");
+        exit(1)
+    }
+    if options.is_present("msgpack") {
+        ResultFormat::MessagePack {
+            zstd: options.is_present("zstd"),
+        }
+    } else if options.is_present("json") {
+        ResultFormat::Json
+    } else {
+        ResultFormat::Ndjson
+    }
+}
+
+/// Writes runs to a result file as soon as they are finished.
+enum RunWriter {
+    Ndjson(NdjsonWriter<File>),
+    MessagePack(MessagePackWriter<Box<dyn Write + Send>>),
+}
+
+impl RunWriter {
+    /// Creates the writer for a streaming `format`. Newline delimited JSON is
+    /// appended to an existing file with `append`.
+    fn open(
+        format: ResultFormat,
+        file_name: &str,
+        header: &ResultHeader,
+        append: bool,
+    ) -> Result<RunWriter, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(append)
+            .write(true)
+            .truncate(!append)
+            .open(file_name)?;
+        match format {
+            ResultFormat::MessagePack { zstd } => {
+                let writer: Box<dyn Write + Send> = if zstd {
+                    compressed(file)?
+                } else {
+                    Box::new(BufWriter::new(file))
+                };
+                Ok(RunWriter::MessagePack(MessagePackWriter::new(
+                    writer, header,
+                )?))
+            }
+            _ => Ok(RunWriter::Ndjson(NdjsonWriter::new(file))),
+        }
+    }
+
+    fn write_run(&mut self, run: &Run) -> Result<(), Box<dyn Error>> {
+        match *self {
+            RunWriter::Ndjson(ref mut writer) => Ok(writer.write_run(run)?),
+            RunWriter::MessagePack(ref mut writer) => writer.write_run(run),
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn compressed(file: File) -> Result<Box<dyn Write + Send>, Box<dyn Error>> {
+    Ok(Box::new(zstd::Encoder::new(file, 0)?.auto_finish()))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compressed(_: File) -> Result<Box<dyn Write + Send>, Box<dyn Error>> {
+    Err("cja was built without the zstd feature".into())
+}
+
+/// Generates and analyzes one run per job of (number of transactions,
+/// transaction size, shuffling) in parallel and writes them in `format`.
+/// With `shapes`, the name of a shapes file and its sampler, the sizes of the
+/// sub-transactions are drawn from it instead.
+fn generate(
+    sampler: &dyn CoinSampler,
+    shapes: Option<(&str, &ShapeSampler)>,
+    analyzer: &Analyzer,
+    jobs: Vec<(u64, u64, &String)>,
+    format: ResultFormat,
+    file_name: &str,
+    header: &ResultHeader,
+    append: bool,
+) {
+    let run_job = |(num_transactions, transaction_size, shuffled): (u64, u64, &String)| {
+        run(
+            sampler,
+            shapes,
+            analyzer,
+            num_transactions,
+            transaction_size,
+            shuffled,
+        )
+    };
+    write_runs(jobs, run_job, format, file_name, header, append);
+}
+
+/// Turns the jobs into runs in parallel and writes them in `format`, each as
+/// soon as it is finished unless they are written as one JSON array.
+fn write_runs<J, F>(
+    jobs: Vec<J>,
+    run_job: F,
+    format: ResultFormat,
+    file_name: &str,
+    header: &ResultHeader,
+    append: bool,
+) where
+    J: Send,
+    F: Fn(J) -> Run + Sync + Send,
+{
+    // Runs cancelled by Ctrl-C are incomplete and not written, so that
+    // `--resume` generates them again.
+    let cancelled = AtomicUsize::new(0);
+    let keep = |run: &Run| {
+        if run.search_status == SearchStatus::Cancelled {
+            cancelled.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    };
+    if let ResultFormat::Json = format {
+        let mut result: Vec<Run> = Vec::new();
+        jobs.into_par_iter()
+            .weight_max()
+            .map(run_job)
+            .collect_into(&mut result);
+        result.retain(|run| keep(run));
+        let mut file = File::create(file_name).unwrap();
+        let json_string = serde_json::to_string(&result).unwrap();
+        let _ = file.write(json_string.as_bytes());
+    } else {
+        let writer = match RunWriter::open(format, file_name, header, append) {
+            Ok(writer) => Mutex::new(writer),
+            Err(err) => return println!("Error while opening {}: {}", file_name, err),
+        };
+        jobs.into_par_iter().weight_max().for_each(|job| {
+            let run = run_job(job);
+            if !keep(&run) {
+                return;
+            }
+            let mut writer = writer.lock().expect("Result writer is poisoned");
+            if let Err(err) = writer.write_run(&run) {
+                println!("Error while writing run: {}", err);
+                exit(1)
+            }
+        });
+    }
+    let cancelled = cancelled.into_inner();
+    if cancelled > 0 {
+        println!("Interrupted, {} runs were not written", cancelled);
+    }
+}
+
+/// A token for `AnalyzerOptions` that the first Ctrl-C cancels, which stops
+/// the running searches. A second Ctrl-C exits immediately.
+fn cancel_on_interrupt() -> CancellationToken {
+    let token = CancellationToken::new();
+    let handler_token = token.clone();
+    let result = ctrlc::set_handler(move || {
+        if handler_token.is_cancelled() {
+            exit(130)
+        }
+        eprintln!("Stopping the searches, press Ctrl-C again to exit immediately");
+        handler_token.cancel();
+    });
+    if let Err(err) = result {
+        eprintln!("Unable to handle Ctrl-C: {}", err);
+    }
+    token
+}
+
+/// Reads the search budget flags shared by the `auto` and `analyze` commands.
+fn search_limits(options: &ArgMatches) -> Result<SearchLimits, clap::Error> {
+    let optional_u64 = |name: &str| {
+        if options.is_present(name) {
+            value_t!(options.value_of(name), u64).map(Some)
+        } else {
+            Ok(None)
+        }
+    };
+    Ok(SearchLimits {
+        max_duration: optional_u64("timeout")?.map(Duration::from_secs),
+        max_filter_calls: optional_u64("max-filter-calls")?,
+        max_partitions: optional_u64("max-partitions")?,
+        max_coins: optional_u64("max-coins")?,
+    })
+}
+
+fn search_limit_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("timeout")
+            .long("timeout")
+            .takes_value(true)
+            .help("stop searching for mappings after this many seconds"),
+        Arg::with_name("max-filter-calls")
+            .long("max-filter-calls")
+            .takes_value(true)
+            .help("stop searching for mappings after this many filter queries"),
+        Arg::with_name("max-partitions")
+            .long("max-partitions")
+            .takes_value(true)
+            .help("stop searching for mappings after this many input or output partitions"),
+        Arg::with_name("max-coins")
+            .long("max-coins")
+            .takes_value(true)
+            .help("skip transactions with more inputs or outputs, at most 64 are supported"),
+    ]
+}
+
+fn result_format_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("json")
+            .long("json")
+            .help("write all runs as one JSON array once they are finished instead of a line of JSON per run"),
+        Arg::with_name("msgpack")
+            .long("msgpack")
+            .conflicts_with("json")
+            .help("write the runs as MessagePack with a header describing the parameters"),
+        Arg::with_name("zstd")
+            .long("zstd")
+            .requires("msgpack")
+            .help("compress the MessagePack output with zstd"),
+    ]
 }
 
 fn get_app<'a>() -> App<'a, 'a> {
@@ -178,92 +1009,264 @@ fn get_app<'a>() -> App<'a, 'a> {
                         .default_value("distribution.bin")
                         .takes_value(true),
                 )
-                .arg(Arg::with_name("output").short("o").takes_value(true)),
+                .arg(
+                    Arg::with_name("shapes")
+                        .long("shapes")
+                        .takes_value(true)
+                        .help("draw the numbers of inputs and outputs of each transaction from this shapes file instead of -s"),
+                )
+                .arg(
+                    Arg::with_name("max-inputs")
+                        .long("max-inputs")
+                        .default_value("8")
+                        .takes_value(true)
+                        .help("only draw shapes with at most this many inputs"),
+                )
+                .arg(
+                    Arg::with_name("max-outputs")
+                        .long("max-outputs")
+                        .default_value("4")
+                        .takes_value(true)
+                        .help("only draw shapes with at most this many outputs"),
+                )
+                .arg(
+                    Arg::with_name("smoothing")
+                        .long("smoothing")
+                        .takes_value(true)
+                        .conflicts_with("sampler")
+                        .help("sample the distribution with its point masses and kernel smoothing of this bandwidth, e.g. 0.05"),
+                )
+                .arg(
+                    Arg::with_name("sampler")
+                        .long("sampler")
+                        .takes_value(true)
+                        .help("JSON description of the coin sampler, or a file containing it, used instead of -d"),
+                )
+                .arg(Arg::with_name("output").short("o").takes_value(true))
+                .args(&result_format_args())
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .conflicts_with_all(&["json", "msgpack"])
+                        .help("keep the runs already in the output and only generate the missing ones"),
+                )
+                .args(&search_limit_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("sweep")
+                .about("generate and analyze CoinJoin transactions for every combination of parameters")
+                .arg(
+                    Arg::with_name("transactions")
+                        .short("t")
+                        .default_value("2-4")
+                        .takes_value(true)
+                        .help("numbers of transactions, e.g. 2,3 or 2-5"),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .short("s")
+                        .default_value("2-3")
+                        .takes_value(true)
+                        .help("numbers of inputs per transaction, e.g. 2,3 or 2-5"),
+                )
+                .arg(
+                    Arg::with_name("shuffled")
+                        .short("S")
+                        .default_value("none")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .possible_values(&["none", "output", "input", "distributed"]),
+                )
+                .arg(
+                    Arg::with_name("runs")
+                        .short("r")
+                        .default_value("5")
+                        .takes_value(true)
+                        .help("runs per combination"),
+                )
+                .arg(
+                    Arg::with_name("parallelism")
+                        .short("p")
+                        .default_value("5")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("distribution")
+                        .short("d")
+                        .default_value("distribution.bin")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("smoothing")
+                        .long("smoothing")
+                        .takes_value(true)
+                        .conflicts_with("sampler")
+                        .help("sample the distribution with its point masses and kernel smoothing of this bandwidth, e.g. 0.05"),
+                )
+                .arg(
+                    Arg::with_name("sampler")
+                        .long("sampler")
+                        .takes_value(true)
+                        .help("JSON description of the coin sampler, or a file containing it, used instead of -d"),
+                )
+                .arg(Arg::with_name("output").short("o").takes_value(true))
+                .args(&result_format_args())
+                .args(&search_limit_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("chain")
+                .about("analyze CoinJoin transactions of the blockchain, e.g. found by detect_coinjoins")
+                .arg(
+                    Arg::with_name("blocks")
+                        .multiple(true)
+                        .required(true)
+                        .help("block files, directories with blk*.dat files or patterns like blocks/blk00*.dat"),
+                )
+                .arg(
+                    Arg::with_name("candidates")
+                        .short("c")
+                        .long("candidates")
+                        .takes_value(true)
+                        .required(true)
+                        .help("file with a txid and optionally its height per line, or the output of detect_coinjoins, - for standard input"),
+                )
+                .arg(
+                    Arg::with_name("parallelism")
+                        .short("p")
+                        .default_value("5")
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("output").short("o").takes_value(true))
+                .args(&result_format_args())
+                .args(&search_limit_args()),
         )
         .subcommand(
             SubCommand::with_name("analyze")
                 .about("analyze single CoinJoin transaction for given inputs and outputs ")
-                .arg(Arg::with_name("inputs").short("i").takes_value(true))
-                .arg(Arg::with_name("outputs").short("o").takes_value(true)),
+                .arg(
+                    Arg::with_name("inputs")
+                        .short("i")
+                        .takes_value(true)
+                        .help("comma separated amounts in satoshis, or in bitcoin with a decimal point"),
+                )
+                .arg(
+                    Arg::with_name("outputs")
+                        .short("o")
+                        .takes_value(true)
+                        .help("comma separated amounts in satoshis, or in bitcoin with a decimal point"),
+                )
+                .arg(
+                    Arg::with_name("parallelism")
+                        .short("p")
+                        .default_value("5")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("fee-as-output")
+                        .long("fee-as-output")
+                        .help("analyze the difference of inputs and outputs as an additional output"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .default_value("text")
+                        .possible_values(&["text", "json", "tsv"])
+                        .help("how to print the mappings"),
+                )
+                .arg(
+                    Arg::with_name("matrix")
+                        .long("matrix")
+                        .takes_value(true)
+                        .possible_values(&["json", "csv"])
+                        .help("print the link probability matrices of the non-derived mappings instead of the mappings"),
+                )
+                .arg(
+                    Arg::with_name("entropy")
+                        .long("entropy")
+                        .takes_value(true)
+                        .default_value("non-derived")
+                        .possible_values(&["non-derived", "boltzmann"])
+                        .help("which mappings count as interpretations for entropy and anonymity sets"),
+                )
+                .args(&search_limit_args()),
         )
 }
 
 fn run(
-    distribution: &Distribution,
+    sampler: &dyn CoinSampler,
+    shapes: Option<(&str, &ShapeSampler)>,
+    analyzer: &Analyzer,
     num_transactions: u64,
     transaction_size: u64,
     shuffled: &String,
 ) -> Run {
     let (transactions, in_coins, out_coins) = match shuffled.as_ref() {
+        _ if shapes.is_some() => {
+            sampler.random_coinjoin_transaction_with_shapes(num_transactions, shapes.unwrap().1)
+        }
         "output" => {
-            distribution.random_coinjoin_transaction_shuffled(num_transactions, transaction_size)
+            sampler.random_coinjoin_transaction_shuffled(num_transactions, transaction_size)
+        }
+        "input" => {
+            sampler.random_coinjoin_transaction_input_shuffled(num_transactions, transaction_size)
         }
-        "input" => distribution
-            .random_coinjoin_transaction_input_shuffled(num_transactions, transaction_size),
-        "distributed" => distribution
+        "distributed" => sampler
             .random_coinjoin_transaction_distributed_shuffled(num_transactions, transaction_size),
-        "none" => distribution.random_coinjoin_transaction(num_transactions, transaction_size),
+        "none" => sampler.random_coinjoin_transaction(num_transactions, transaction_size),
         _ => panic!("Invalid value for shuffled options"),
     };
 
-    let now = Instant::now();
-    let in_partitions: Vec<Partition> = {
-        SumFilteredPartitionIterator::new(in_coins.clone(), &SubsetSumsFilter::new(&out_coins))
-            .collect()
-    };
-    let out_partitions: Vec<Partition> = {
-        SumFilteredPartitionIterator::new(
-            out_coins.clone(),
-            &PartitionsSubsetSumsFilter::new(&in_partitions),
-        )
-        .collect()
-    };
-    let mut partition_tuples: Vec<(Partition, Partition)> = Vec::new();
-    for in_partition in in_partitions {
-        for out_partition in out_partitions.clone() {
-            if partitions_match(&in_partition, &out_partition) {
-                partition_tuples.push((in_partition.clone(), out_partition.clone()));
-            }
-        }
-    }
-    let duration = now.elapsed();
+    let analysis = analyzer.analyze(&in_coins, &out_coins);
     Run {
         num_transactions,
         num_inputs_per_transaction: transaction_size,
+        shuffled: Some(shuffled.clone()),
+        shapes: shapes.map(|(file_name, _)| file_name.to_string()),
         original_transactions: transactions,
         in_coins,
         out_coins,
-        partition_tuples,
-        duration_secs: duration.as_secs(),
-        duration_nano: duration.subsec_nanos(),
+        partition_tuples: analysis.partition_tuples,
+        pairings: analysis.pairings,
+        duration_secs: analysis.duration.as_secs(),
+        duration_nano: analysis.duration.subsec_nanos(),
+        search_status: analysis.search_status,
+        txid: None,
+        height: None,
     }
 }
 
-fn read_distribution(file_name: &str) -> Result<Distribution, String> {
-    match load_from_rmp::<Distribution>(Path::new(file_name)) {
-        Ok(dist) => Ok(dist),
-        Err(e) => Err(format!("Could not parse distribution: {}", e)),
-    }
-}
-
-fn load_from_rmp<T>(file: &Path) -> Result<T, Box<dyn Error>>
-where
-    T: DeserializeOwned,
-{
-    let file_handler = OpenOptions::new().read(true).open(file)?;
-    let buf_reader = BufReader::new(&file_handler);
-    let data: T = rmps::from_read(buf_reader)?;
-    Ok(data)
+/// Reads a shapes file written by `build_distribution` and limits it to the
+/// shapes allowed by `--max-inputs` and `--max-outputs`.
+fn read_shape_sampler(
+    options: &ArgMatches,
+    file_name: &str,
+) -> Result<ShapeSampler, Box<dyn Error>> {
+    let histogram = read_shapes(File::open(file_name)?)?;
+    let max_inputs = value_t!(options.value_of("max-inputs"), u64)?;
+    let max_outputs = value_t!(options.value_of("max-outputs"), u64)?;
+    Ok(ShapeSampler::new(&histogram, max_inputs, max_outputs)?)
 }
 
-fn partitions_match(a: &Partition, b: &Partition) -> bool {
-    'outer: for set_a in a {
-        for set_b in b {
-            if set_a.iter().sum::<u64>() == set_b.iter().sum::<u64>() {
-                continue 'outer;
-            }
-        }
-        return false;
-    }
-    true
+/// Creates the coin sampler given by `--sampler`, either as JSON or as the
+/// path of a JSON file, or the distribution file given by `-d` otherwise.
+fn read_sampler(
+    options: &ArgMatches,
+) -> Result<(SamplerSpec, Box<dyn CoinSampler>), Box<dyn Error>> {
+    let spec = match options.value_of("sampler") {
+        Some(json) if json.trim_start().starts_with('{') => serde_json::from_str(json)?,
+        Some(file_name) => serde_json::from_reader(BufReader::new(File::open(file_name)?))?,
+        None => SamplerSpec::Distribution {
+            path: options
+                .value_of("distribution")
+                .unwrap_or_default()
+                .to_string(),
+            smoothing: match options.value_of("smoothing") {
+                Some(_) => Some(value_t!(options.value_of("smoothing"), f64)?),
+                None => None,
+            },
+        },
+    };
+    let sampler = spec.build()?;
+    Ok((spec, sampler))
 }
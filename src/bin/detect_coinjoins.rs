@@ -0,0 +1,151 @@
+extern crate coinjoin_analyzer;
+use coinjoin_analyzer::{
+    detect, expand_block_files, locate_main_chain, BlockLocation, Candidate, ChainIterator,
+    Heuristic, Progress, UtxoSet,
+};
+#[macro_use(value_t)]
+extern crate clap;
+extern crate serde_json;
+use clap::{App, Arg};
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{stderr, stdout, BufWriter, Write};
+use std::path::Path;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = get_app().get_matches();
+    let only = match matches.values_of("only") {
+        Some(names) => Some(
+            names
+                .map(|name| name.parse())
+                .collect::<Result<Vec<Heuristic>, String>>()?,
+        ),
+        None => None,
+    };
+    let mut output: Box<dyn Write> = match matches.value_of("output") {
+        Some(file) if Path::new(file).exists() && !matches.is_present("force") => {
+            return Err(format!("{} already exists, use --force to overwrite it", file).into());
+        }
+        Some(file) => Box::new(BufWriter::new(File::create(file)?)),
+        None => Box::new(BufWriter::new(stdout())),
+    };
+
+    let arguments: Vec<&str> = matches.values_of("blocks").unwrap().collect();
+    let files = expand_block_files(&arguments).map_err(|error| error.to_string())?;
+    let mut progress = Progress::new(stderr(), "Reading block headers", files.len());
+    let chain =
+        locate_main_chain(&files, || progress.advance()).map_err(|error| error.to_string())?;
+    if chain.is_empty() {
+        return Err("The block files contain no main chain".into());
+    }
+    let start = value_t!(matches, "start-height", u64)?;
+    let end = match matches.value_of("end-height") {
+        Some(_) => value_t!(matches, "end-height", u64)?,
+        None => chain.len() as u64 - 1,
+    };
+    if chain.len() as u64 <= end {
+        return Err(format!(
+            "The block files only reach height {}",
+            chain.len() as i64 - 1
+        )
+        .into());
+    }
+    // The spent coins are only known if all blocks before are applied.
+    let mut utxos = if matches.is_present("resolve-values") {
+        Some(UtxoSet::new())
+    } else {
+        None
+    };
+    let first = if utxos.is_some() { 0 } else { start };
+    let blocks: Vec<(u64, usize, BlockLocation)> = chain
+        .into_iter()
+        .enumerate()
+        .map(|(height, (file, location))| (height as u64, file, location))
+        .filter(|&(height, _, _)| first <= height && height <= end)
+        .collect();
+
+    let mut found: BTreeMap<Heuristic, usize> = BTreeMap::new();
+    let mut progress = Progress::new(stderr(), "Scanning blocks", blocks.len());
+    for block in ChainIterator::new(files, blocks) {
+        let (height, block) = block?;
+        for transaction in block.transactions.iter() {
+            if height >= start {
+                let input_values = utxos
+                    .as_ref()
+                    .and_then(|utxos| utxos.input_values(transaction));
+                let heuristic = detect(transaction, input_values.as_ref().map(|v| &v[..])).filter(
+                    |heuristic| only.as_ref().map_or(true, |only| only.contains(heuristic)),
+                );
+                if let Some(heuristic) = heuristic {
+                    let candidate = Candidate::new(transaction, height, heuristic);
+                    serde_json::to_writer(&mut output, &candidate)?;
+                    writeln!(output)?;
+                    *found.entry(heuristic).or_insert(0) += 1;
+                }
+            }
+            if let Some(utxos) = utxos.as_mut() {
+                utxos.apply_transaction(transaction);
+            }
+        }
+        progress.advance();
+    }
+    output.flush()?;
+    for (heuristic, count) in found.iter() {
+        eprintln!("{}: {}", heuristic, count);
+    }
+    Ok(())
+}
+
+fn get_app<'a>() -> App<'a, 'a> {
+    App::new("detect_coinjoins")
+        .author("Felix Konstantin Maurer <maufl@maufl.de>")
+        .about("This program scans bitcoind block files for likely CoinJoin and PayJoin transactions.")
+        .version("v0.1")
+        .arg(
+            Arg::with_name("blocks")
+                .multiple(true)
+                .required(true)
+                .help("block files, directories with blk*.dat files or patterns like blocks/blk00*.dat"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("file to write the candidates to as lines of JSON, standard output by default"),
+        )
+        .arg(
+            Arg::with_name("force")
+                .short("f")
+                .long("force")
+                .help("overwrite an existing output file"),
+        )
+        .arg(
+            Arg::with_name("start-height")
+                .long("start-height")
+                .takes_value(true)
+                .default_value("0")
+                .help("first height to scan"),
+        )
+        .arg(
+            Arg::with_name("end-height")
+                .long("end-height")
+                .takes_value(true)
+                .help("last height to scan, the tip of the main chain by default"),
+        )
+        .arg(
+            Arg::with_name("only")
+                .long("only")
+                .takes_value(true)
+                .use_delimiter(true)
+                .possible_values(&["whirlpool", "wasabi", "join_market", "equal_outputs", "pay_join"])
+                .help("only report candidates of these heuristics"),
+        )
+        .arg(
+            Arg::with_name("resolve-values")
+                .long("resolve-values")
+                .help("track the unspent coins from the genesis block on to know the input values, which detects PayJoins but needs a lot of memory"),
+        )
+}
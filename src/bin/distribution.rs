@@ -0,0 +1,151 @@
+extern crate serde_json;
+
+#[macro_use(value_t)]
+extern crate clap;
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+extern crate coinjoin_analyzer;
+use coinjoin_analyzer::{
+    downsample, earth_movers_distance, ks_statistic, Distribution, DistributionSummary,
+};
+
+use std::fs::File;
+use std::process::exit;
+
+fn main() {
+    let matches = get_app().get_matches();
+    match matches.subcommand() {
+        ("summary", Some(options)) => summary(options),
+        ("csv", Some(options)) => csv(options),
+        ("compare", Some(options)) => compare(options),
+        _ => {
+            let _ = get_app().print_help();
+        }
+    }
+}
+
+fn summary(options: &ArgMatches) {
+    let distribution = read_distribution(options.value_of("distribution").unwrap_or_default());
+    let dust = value_t!(options.value_of("dust"), u64).unwrap_or_else(|e| e.exit());
+    let round_unit = value_t!(options.value_of("round-unit"), u64).unwrap_or_else(|e| e.exit());
+    let top = value_t!(options.value_of("top"), usize).unwrap_or_else(|e| e.exit());
+    let summary = DistributionSummary::new(&distribution, dust, round_unit, top);
+    if options.is_present("json") {
+        return println!(
+            "{}",
+            serde_json::to_string(&summary).expect("Unable to serialize summary")
+        );
+    }
+    println!("Points: {}", summary.points);
+    println!("Mean: {:.0}", summary.mean);
+    for (p, amount) in summary.quantiles.iter() {
+        println!("Quantile {:.2}: {}", p, amount);
+    }
+    println!(
+        "Mass below dust threshold of {}: {:.6}",
+        summary.dust_threshold, summary.dust_mass
+    );
+    println!("Round amounts, multiples of {}:", summary.round_unit);
+    for (amount, mass) in summary.round_amounts.iter() {
+        println!("{}\t{:.6}", amount, mass);
+    }
+}
+
+fn csv(options: &ArgMatches) {
+    let distribution = read_distribution(options.value_of("distribution").unwrap_or_default());
+    let points = value_t!(options.value_of("points"), usize).unwrap_or_else(|e| e.exit());
+    println!("amount,cumulative_probability");
+    for (amount, probability) in downsample(&distribution, points) {
+        println!("{},{}", amount, probability);
+    }
+}
+
+fn compare(options: &ArgMatches) {
+    let a = read_distribution(options.value_of("first").unwrap_or_default());
+    let b = read_distribution(options.value_of("second").unwrap_or_default());
+    println!("KS statistic: {:.6}", ks_statistic(&a, &b));
+    println!(
+        "Earth mover's distance: {:.0} satoshis",
+        earth_movers_distance(&a, &b)
+    );
+}
+
+/// Accepts whole numbers above zero.
+fn positive(value: String) -> Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(0) => Err("must be greater than 0".to_string()),
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn get_app<'a>() -> App<'a, 'a> {
+    let distribution_arg = Arg::with_name("distribution")
+        .short("d")
+        .default_value("distribution.bin")
+        .takes_value(true);
+    App::new("distribution")
+        .about("Inspects and compares coin size distributions written by build_distribution")
+        .subcommand(
+            SubCommand::with_name("summary")
+                .about("print quantiles, mean, dust and the most frequent round amounts")
+                .arg(distribution_arg.clone())
+                .arg(
+                    Arg::with_name("dust")
+                        .long("dust")
+                        .takes_value(true)
+                        .default_value("546")
+                        .help("dust threshold in satoshis, by default the dust limit of P2PKH outputs"),
+                )
+                .arg(
+                    Arg::with_name("round-unit")
+                        .long("round-unit")
+                        .takes_value(true)
+                        .default_value("100000")
+                        .validator(positive)
+                        .help("amounts that are multiples of this many satoshis count as round"),
+                )
+                .arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .takes_value(true)
+                        .default_value("10")
+                        .help("number of round amounts to print"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("print the summary as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("csv")
+                .about("print the cumulative distribution at equally spaced probabilities")
+                .arg(distribution_arg)
+                .arg(
+                    Arg::with_name("points")
+                        .long("points")
+                        .takes_value(true)
+                        .default_value("1000"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("print the KS statistic and earth mover's distance of two distributions")
+                .arg(Arg::with_name("first").required(true).index(1))
+                .arg(Arg::with_name("second").required(true).index(2)),
+        )
+}
+
+fn read_distribution(file_name: &str) -> Distribution {
+    match File::open(file_name)
+        .map_err(Box::from)
+        .and_then(coinjoin_analyzer::read_distribution)
+    {
+        Ok(dist) => dist,
+        Err(e) => {
+            eprintln!("Could not parse distribution {}: {}", file_name, e);
+            exit(1)
+        }
+    }
+}
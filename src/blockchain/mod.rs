@@ -1,9 +1,18 @@
 use nom::{le_i64, le_u16, le_u32, le_u64, le_u8, IResult, Needed};
+use sha2::{Digest, Sha256};
 use std::borrow::BorrowMut;
+use std::collections::HashMap;
 use std::fmt;
+use std::fs;
 use std::fs::File;
-use std::io::{Error, Read};
-use std::path::Path;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod test;
+
+const MAGIC: [u8; 4] = [0xf9, 0xbe, 0xb4, 0xd9];
+const HEADER_SIZE: usize = 80;
 
 #[derive(Debug)]
 pub struct BlockHeader {
@@ -39,7 +48,7 @@ impl fmt::Display for BlockHeader {
 
 fn reverse_hash(hash: &[u8; 32]) -> [u8; 32] {
     let mut result: [u8; 32] = [0; 32];
-    for i in 0..15 {
+    for i in 0..16 {
         result[i] = hash[31 - i];
         result[31 - i] = hash[i];
     }
@@ -66,6 +75,41 @@ named!(pub parse_block_header<&[u8], BlockHeader>,
        )
 );
 
+/// The hash of a serialized block header, in the byte order of
+/// `BlockHeader::previous_block_header_hash`.
+pub fn block_hash(header: &[u8]) -> [u8; 32] {
+    double_sha256(&[header])
+}
+
+/// Formats a hash like block explorers do.
+pub fn hash_to_hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parses a hash formatted like `hash_to_hex` does.
+pub fn hash_from_hex(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0u8; 32];
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * index..2 * index + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// The double SHA-256 of the concatenated parts, reversed like all hashes.
+fn double_sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Sha256::digest(hasher.finalize()));
+    reverse_hash(&hash)
+}
+
 #[derive(Debug)]
 pub struct Outpoint {
     pub hash: [u8; 32],
@@ -88,6 +132,14 @@ pub struct TransactionInput {
     pub previous_output: Outpoint,
     pub sequence: u32,
     pub script: Vec<u8>,
+    /// The witness stack, empty for transactions without witness.
+    pub witness: Vec<Vec<u8>>,
+}
+
+impl TransactionInput {
+    pub fn is_coinbase(&self) -> bool {
+        self.previous_output.hash == [0u8; 32] && self.previous_output.index == 0xffff_ffff
+    }
 }
 
 fn parse_compact(input: &[u8]) -> IResult<&[u8], u64> {
@@ -112,7 +164,8 @@ named!(pub parse_transaction_input<&[u8], TransactionInput>,
                (TransactionInput{
                    previous_output,
                    script: script.to_vec(),
-                   sequence
+                   sequence,
+                   witness: Vec::new()
                })
        )
 );
@@ -137,29 +190,65 @@ named!(pub parse_transaction_output<&[u8], TransactionOutput>,
 
 #[derive(Debug)]
 pub struct Transaction {
+    /// The hash of the transaction without witness data, in the byte order
+    /// of `Outpoint::hash`.
+    pub txid: [u8; 32],
     pub version: u32,
     pub lock_time: u32,
     pub inputs: Vec<TransactionInput>,
     pub outputs: Vec<TransactionOutput>,
 }
 
-named!(pub parse_transaction<&[u8], Transaction>,
+named!(parse_inputs<&[u8], Vec<TransactionInput>>,
+       length_count!(parse_compact, parse_transaction_input)
+);
+
+named!(parse_outputs<&[u8], Vec<TransactionOutput>>,
+       length_count!(parse_compact, parse_transaction_output)
+);
+
+named!(parse_witness_item<&[u8], Vec<u8>>,
        do_parse!(
-           version: le_u32 >>
-               tx_in_count: parse_compact >>
-               inputs: count!(parse_transaction_input, tx_in_count as usize) >>
-               tx_out_count: parse_compact >>
-               outputs: count!(parse_transaction_output, tx_out_count as usize) >>
-               lock_time: le_u32 >>
-               (Transaction{
-                   version,
-                   lock_time,
-                   inputs,
-                   outputs
-               })
+           size: parse_compact >>
+               item: take!(size) >>
+               (item.to_vec())
        )
 );
 
+named!(parse_witness<&[u8], Vec<Vec<u8>>>,
+       length_count!(parse_compact, parse_witness_item)
+);
+
+pub fn parse_transaction(input: &[u8]) -> IResult<&[u8], Transaction> {
+    let (rest, version) = try_parse!(input, le_u32);
+    // Transactions with witness have a marker and a flag byte in front of
+    // their inputs, see BIP 144.
+    let has_witness = rest.len() >= 2 && rest[0] == 0 && rest[1] == 1;
+    let body = if has_witness { &rest[2..] } else { rest };
+    let (rest, mut inputs) = try_parse!(body, parse_inputs);
+    let (mut rest, outputs) = try_parse!(rest, parse_outputs);
+    let body = &body[..body.len() - rest.len()];
+    if has_witness {
+        for transaction_input in inputs.iter_mut() {
+            let (remaining, witness) = try_parse!(rest, parse_witness);
+            transaction_input.witness = witness;
+            rest = remaining;
+        }
+    }
+    let (remaining, lock_time) = try_parse!(rest, le_u32);
+    let txid = double_sha256(&[&input[..4], body, &rest[..4]]);
+    IResult::Done(
+        remaining,
+        Transaction {
+            txid,
+            version,
+            lock_time,
+            inputs,
+            outputs,
+        },
+    )
+}
+
 #[derive(Debug)]
 pub struct Block {
     pub header: BlockHeader,
@@ -190,6 +279,113 @@ impl BlockFileIterator {
             Err(err) => Err(err),
         }
     }
+
+    /// Continues with the block at `offset`, as given by `scan_block_file`.
+    pub fn seek(&mut self, offset: u64) -> Result<(), Error> {
+        self.file.seek(SeekFrom::Start(offset)).map(|_| ())
+    }
+}
+
+/// Where a block is stored in a block file and how it links to its parent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockLocation {
+    pub hash: [u8; 32],
+    pub previous_block_header_hash: [u8; 32],
+    pub offset: u64,
+}
+
+/// Reads only the headers of the blocks in a block file, skipping their
+/// transactions, to locate the blocks without parsing them.
+pub fn scan_block_file<P: AsRef<Path>>(path: P) -> Result<Vec<BlockLocation>, Error> {
+    let mut file = File::open(path)?;
+    let mut locations = Vec::new();
+    let mut offset = 0u64;
+    loop {
+        let mut prefix = [0u8; 8];
+        match file.read_exact(&mut prefix) {
+            Ok(()) => {}
+            Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error),
+        }
+        // Block files are preallocated and end with zeros.
+        if prefix[..4] != MAGIC {
+            break;
+        }
+        let size = le_u32(&prefix[4..]).to_full_result().unwrap_or(0) as usize;
+        if size < HEADER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "Block is too small"));
+        }
+        let mut header = [0u8; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+        let parsed = match parse_block_header(&header).to_full_result() {
+            Ok(parsed) => parsed,
+            Err(_) => return Err(Error::new(ErrorKind::InvalidData, "Invalid block header")),
+        };
+        locations.push(BlockLocation {
+            hash: block_hash(&header),
+            previous_block_header_hash: parsed.previous_block_header_hash,
+            offset,
+        });
+        file.seek(SeekFrom::Current((size - HEADER_SIZE) as i64))?;
+        offset += (8 + size) as u64;
+    }
+    Ok(locations)
+}
+
+/// Orders blocks by height along the longest chain, so that the `i`th index
+/// of the result is the index in `blocks` of the block at height `i`. Stale
+/// blocks and blocks whose ancestors are missing are left out.
+pub fn main_chain(blocks: &[BlockLocation]) -> Vec<usize> {
+    let by_hash: HashMap<[u8; 32], usize> = blocks
+        .iter()
+        .enumerate()
+        .map(|(index, block)| (block.hash, index))
+        .collect();
+    // None while unknown, Some(None) if an ancestor is missing.
+    let mut heights: Vec<Option<Option<i64>>> = vec![None; blocks.len()];
+    for start in 0..blocks.len() {
+        // Walk back to a block with known height, then assign heights on the
+        // way back.
+        let mut path = Vec::new();
+        let mut current = start;
+        let mut height = loop {
+            if let Some(height) = heights[current] {
+                break height;
+            }
+            path.push(current);
+            let previous = &blocks[current].previous_block_header_hash;
+            if *previous == [0u8; 32] {
+                break Some(-1);
+            }
+            match by_hash.get(previous) {
+                Some(&index) => current = index,
+                None => break None,
+            }
+        };
+        while let Some(index) = path.pop() {
+            height = height.map(|height| height + 1);
+            heights[index] = Some(height);
+        }
+    }
+    let tip = heights
+        .iter()
+        .enumerate()
+        .filter_map(|(index, height)| {
+            height
+                .and_then(|height| height)
+                .map(|height| (height, index))
+        })
+        .max();
+    let mut chain = Vec::new();
+    let mut current = tip.map(|(_, index)| index);
+    while let Some(index) = current {
+        chain.push(index);
+        current = by_hash
+            .get(&blocks[index].previous_block_header_hash)
+            .cloned();
+    }
+    chain.reverse();
+    chain
 }
 
 impl Iterator for BlockFileIterator {
@@ -204,7 +400,7 @@ impl Iterator for BlockFileIterator {
         if n == 0 {
             return None;
         }
-        if buff != [0xf9, 0xbe, 0xb4, 0xd9] {
+        if buff != MAGIC {
             return None;
         }
         let _ = self
@@ -224,3 +420,137 @@ impl Iterator for BlockFileIterator {
         Some(block)
     }
 }
+
+/// Locates the blocks of the main chain in block files, ordered by height, as
+/// the index of their file and their location in it. `scanned` is called
+/// after each file.
+pub fn locate_main_chain<P: AsRef<Path>, F: FnMut()>(
+    files: &[P],
+    mut scanned: F,
+) -> Result<Vec<(usize, BlockLocation)>, Error> {
+    let mut locations: Vec<(usize, BlockLocation)> = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        let file_locations = scan_block_file(file).map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Could not read {}: {}", file.as_ref().display(), error),
+            )
+        })?;
+        for location in file_locations {
+            locations.push((index, location));
+        }
+        scanned();
+    }
+    let blocks: Vec<BlockLocation> = locations
+        .iter()
+        .map(|(_, location)| location.clone())
+        .collect();
+    Ok(main_chain(&blocks)
+        .into_iter()
+        .map(|index| locations[index].clone())
+        .collect())
+}
+
+/// Reads the given blocks, as the height, the index of their file and their
+/// location, in the given order. Since blocks are mostly stored in order,
+/// files are rarely reopened if the blocks are ordered by height.
+pub struct ChainIterator {
+    files: Vec<PathBuf>,
+    blocks: ::std::vec::IntoIter<(u64, usize, BlockLocation)>,
+    current: Option<(usize, BlockFileIterator)>,
+}
+
+impl ChainIterator {
+    pub fn new(files: Vec<PathBuf>, blocks: Vec<(u64, usize, BlockLocation)>) -> ChainIterator {
+        ChainIterator {
+            files,
+            blocks: blocks.into_iter(),
+            current: None,
+        }
+    }
+
+    fn read(&mut self, file: usize, offset: u64) -> Result<Block, Error> {
+        if self.current.as_ref().map(|&(current, _)| current) != Some(file) {
+            self.current = Some((file, BlockFileIterator::open(&self.files[file])?));
+        }
+        let iter = &mut self.current.as_mut().unwrap().1;
+        iter.seek(offset)?;
+        iter.next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Could not read block"))
+    }
+}
+
+impl Iterator for ChainIterator {
+    type Item = Result<(u64, Block), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (height, file, location) = self.blocks.next()?;
+        Some(
+            self.read(file, location.offset)
+                .map(|block| (height, block)),
+        )
+    }
+}
+
+/// Expands directories to the block files they contain and patterns with `*`
+/// or `?` in the file name to the matching files, sorted by name.
+pub fn expand_block_files<S: AsRef<str>>(arguments: &[S]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for argument in arguments {
+        let path = Path::new(argument.as_ref());
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if path.is_dir() {
+            files.extend(matching_files(path, "blk*.dat")?);
+        } else if name.contains('*') || name.contains('?') {
+            let directory = match path.parent() {
+                Some(parent) if parent != Path::new("") => parent,
+                _ => Path::new("."),
+            };
+            let matches = matching_files(directory, name)?;
+            if matches.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("No files match {}", argument.as_ref()),
+                ));
+            }
+            files.extend(matches);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+fn matching_files(directory: &Path, pattern: &str) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let matches = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| {
+                matches_pattern(pattern.as_bytes(), name.as_bytes())
+            });
+        if matches && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            matches_pattern(&pattern[1..], name)
+                || (!name.is_empty() && matches_pattern(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => matches_pattern(&pattern[1..], &name[1..]),
+        (Some(a), Some(b)) if a == b => matches_pattern(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
@@ -0,0 +1,164 @@
+use std::env::temp_dir;
+use std::fs;
+
+use super::*;
+
+const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn location(hash: u8, previous: u8) -> BlockLocation {
+    BlockLocation {
+        hash: [hash; 32],
+        previous_block_header_hash: [previous; 32],
+        offset: 0,
+    }
+}
+
+/// A block file entry of an empty block with the given parent.
+fn serialized_block(previous: &[u8; 32], nonce: u8) -> Vec<u8> {
+    let mut header = vec![1u8, 0, 0, 0];
+    let mut previous = *previous;
+    previous.reverse();
+    header.extend_from_slice(&previous);
+    header.extend_from_slice(&[0u8; 32]);
+    header.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, nonce, 0, 0, 0]);
+    let mut block = MAGIC.to_vec();
+    block.extend_from_slice(&[81, 0, 0, 0]);
+    block.extend_from_slice(&header);
+    block.push(0);
+    block
+}
+
+#[test]
+fn test_block_hash() {
+    let header = from_hex(GENESIS_HEADER);
+    let parsed = parse_block_header(&header).to_full_result().unwrap();
+    assert_eq!(parsed.previous_block_header_hash, [0u8; 32]);
+    assert_eq!(
+        block_hash(&header).to_vec(),
+        from_hex("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f")
+    );
+}
+
+#[test]
+fn test_main_chain() {
+    // 1 is the genesis block, 3 a stale block, 9 has no known ancestor.
+    let blocks = vec![
+        location(4, 2),
+        location(2, 1),
+        location(3, 1),
+        location(1, 0),
+        location(9, 8),
+    ];
+    assert_eq!(main_chain(&blocks), vec![3, 1, 0]);
+    assert!(main_chain(&[location(9, 8)]).is_empty());
+}
+
+#[test]
+fn test_scan_block_file() {
+    let genesis = serialized_block(&[0u8; 32], 1);
+    let genesis_hash = block_hash(&genesis[8..88]);
+    let child = serialized_block(&genesis_hash, 2);
+    let mut file = child.clone();
+    file.extend_from_slice(&genesis);
+    // Block files are preallocated.
+    file.extend_from_slice(&[0u8; 16]);
+    let path = temp_dir().join("cja-test-scan-block-file.dat");
+    fs::write(&path, &file).unwrap();
+
+    let locations = scan_block_file(&path).unwrap();
+    assert_eq!(locations.len(), 2);
+    assert_eq!(locations[0].offset, 0);
+    assert_eq!(locations[1].offset, child.len() as u64);
+    assert_eq!(locations[1].hash, genesis_hash);
+    assert_eq!(locations[0].previous_block_header_hash, genesis_hash);
+    assert_eq!(main_chain(&locations), vec![1, 0]);
+
+    let mut iter = BlockFileIterator::open(&path).unwrap();
+    iter.seek(locations[1].offset).unwrap();
+    let block = iter.next().unwrap();
+    assert_eq!(block.header.nonce, 1);
+    assert!(iter.next().is_none());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_transaction_id() {
+    // The coinbase transaction of the genesis block.
+    let coinbase = from_hex("01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000");
+    let transaction = parse_transaction(&coinbase).to_full_result().unwrap();
+    assert_eq!(
+        transaction.txid.to_vec(),
+        from_hex("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b")
+    );
+    assert!(transaction.inputs[0].is_coinbase());
+    assert_eq!(transaction.outputs[0].value, 5_000_000_000);
+}
+
+#[test]
+fn test_witness_transaction() {
+    let inputs = "01".to_string() + &"11".repeat(32) + "01000000" + "00" + "ffffffff";
+    let outputs = "02".to_string() + "e803000000000000" + "0100" + "d007000000000000" + "00";
+    let legacy = from_hex(&("02000000".to_string() + &inputs + &outputs + "00000000"));
+    // One input with the stack items 01ff and an empty one.
+    let witness = "020201ff00";
+    let with_witness =
+        from_hex(&("02000000".to_string() + "0001" + &inputs + &outputs + witness + "00000000"));
+
+    let expected = parse_transaction(&legacy).to_full_result().unwrap();
+    let transaction = parse_transaction(&with_witness).to_full_result().unwrap();
+    assert_eq!(transaction.txid, expected.txid);
+    assert_eq!(transaction.inputs[0].previous_output.index, 1);
+    assert_eq!(
+        transaction.inputs[0].witness,
+        vec![vec![0x01, 0xff], vec![]]
+    );
+    assert!(expected.inputs[0].witness.is_empty());
+    assert_eq!(transaction.outputs.len(), 2);
+    assert_eq!(transaction.outputs[1].value, 2_000);
+}
+
+#[test]
+fn test_hash_hex() {
+    let hex = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+    let hash = hash_from_hex(hex).unwrap();
+    assert_eq!(hash[..3], [0, 0, 0]);
+    assert_eq!(hash_to_hex(&hash), hex);
+    assert_eq!(hash_from_hex(&hex[2..]), None);
+    assert_eq!(hash_from_hex(&hex.replace("f", "g")), None);
+}
+
+#[test]
+fn test_expand_block_files() {
+    assert!(matches_pattern(b"blk*.dat", b"blk00001.dat"));
+    assert!(matches_pattern(b"blk0000?.dat", b"blk00001.dat"));
+    assert!(!matches_pattern(b"blk*.dat", b"rev00001.dat"));
+    assert!(!matches_pattern(b"blk?.dat", b"blk.dat"));
+
+    let directory = temp_dir().join("cja-test-expand-block-files");
+    fs::create_dir_all(&directory).unwrap();
+    for name in ["blk00001.dat", "blk00000.dat", "rev00000.dat"].iter() {
+        fs::write(directory.join(name), b"").unwrap();
+    }
+    let expected = vec![
+        directory.join("blk00000.dat"),
+        directory.join("blk00001.dat"),
+    ];
+    assert_eq!(
+        expand_block_files(&[directory.to_str().unwrap()]).unwrap(),
+        expected
+    );
+    let pattern = directory.join("blk*1.dat");
+    assert_eq!(
+        expand_block_files(&[pattern.to_str().unwrap()]).unwrap(),
+        vec![directory.join("blk00001.dat")]
+    );
+    assert!(expand_block_files(&[directory.join("x*").to_str().unwrap()]).is_err());
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,166 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Describes whether a partition search enumerated everything or why it
+/// stopped early.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchStatus {
+    Complete,
+    TimedOut,
+    FilterCallLimitReached,
+    PartitionLimitReached,
+    Cancelled,
+    /// The transaction has more inputs or outputs than the search supports
+    /// or `SearchLimits::max_coins` allows, so it was not searched at all.
+    TooManyCoins,
+}
+
+impl SearchStatus {
+    pub fn is_complete(&self) -> bool {
+        *self == SearchStatus::Complete
+    }
+}
+
+impl Default for SearchStatus {
+    /// Results written before searches could be truncated are complete.
+    fn default() -> SearchStatus {
+        SearchStatus::Complete
+    }
+}
+
+/// Upper bounds for a partition search. `None` means unbounded.
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub max_duration: Option<Duration>,
+    pub max_filter_calls: Option<u64>,
+    pub max_partitions: Option<u64>,
+    /// Transactions with more inputs or more outputs are not searched.
+    pub max_coins: Option<u64>,
+}
+
+impl SearchLimits {
+    pub fn unlimited() -> SearchLimits {
+        SearchLimits::default()
+    }
+}
+
+/// A flag that can be shared between threads to stop running searches.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Tracks the resources spent by a search against its `SearchLimits`.
+///
+/// A budget is shared by reference between all iterators taking part in a
+/// search, including nested and parallel ones, so counters are atomic. Once a
+/// limit is hit the budget stays exhausted and remembers the first reason.
+pub struct SearchBudget {
+    limits: SearchLimits,
+    token: CancellationToken,
+    started: Instant,
+    filter_calls: AtomicU64,
+    partitions: AtomicU64,
+    stopped: AtomicBool,
+    status: Mutex<SearchStatus>,
+}
+
+impl SearchBudget {
+    /// The clock for `max_duration` starts when the budget is created.
+    pub fn new(limits: SearchLimits, token: CancellationToken) -> SearchBudget {
+        SearchBudget {
+            limits,
+            token,
+            started: Instant::now(),
+            filter_calls: AtomicU64::new(0),
+            partitions: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+            status: Mutex::new(SearchStatus::Complete),
+        }
+    }
+
+    pub fn unlimited() -> SearchBudget {
+        SearchBudget::new(SearchLimits::unlimited(), CancellationToken::new())
+    }
+
+    /// Returns true if the search has to stop. The check is cheap compared to
+    /// a filter query, so it is done before every step of the enumeration.
+    pub fn exhausted(&self) -> bool {
+        if self.stopped.load(Ordering::Relaxed) {
+            return true;
+        }
+        let status = if self.token.is_cancelled() {
+            SearchStatus::Cancelled
+        } else if self
+            .limits
+            .max_duration
+            .map_or(false, |max| self.started.elapsed() >= max)
+        {
+            SearchStatus::TimedOut
+        } else if self
+            .limits
+            .max_filter_calls
+            .map_or(false, |max| self.filter_calls() >= max)
+        {
+            SearchStatus::FilterCallLimitReached
+        } else if self
+            .limits
+            .max_partitions
+            .map_or(false, |max| self.partitions() >= max)
+        {
+            SearchStatus::PartitionLimitReached
+        } else {
+            return false;
+        };
+        self.stop(status);
+        true
+    }
+
+    fn stop(&self, status: SearchStatus) {
+        let mut current = self.status.lock().expect("Poisoned search status");
+        if current.is_complete() {
+            *current = status;
+        }
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn record_filter_call(&self) {
+        self.filter_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_partition(&self) {
+        self.partitions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn filter_calls(&self) -> u64 {
+        self.filter_calls.load(Ordering::Relaxed)
+    }
+
+    pub fn partitions(&self) -> u64 {
+        self.partitions.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// `Complete` unless a limit was hit or the search was cancelled.
+    pub fn status(&self) -> SearchStatus {
+        *self.status.lock().expect("Poisoned search status")
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use budget::SearchBudget;
+use filters::SubsetSumsFilter;
+use types::{Filter, Set};
+
+#[cfg(test)]
+mod test;
+
+/// The number of mappings of a transaction together with the number of
+/// mappings in which each input is linked to each output.
+///
+/// A mapping is a pair of a partition of the inputs and a partition of the
+/// outputs whose multi-sets of part sums are equal. An input and an output are
+/// linked in a mapping if the parts containing them have the same sum.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MappingCounts {
+    pub mappings: u64,
+    /// Indexed by input, then output, in the order of the given sets.
+    pub links: Vec<Vec<u64>>,
+}
+
+/// Counts the mappings of `inputs` to `outputs` without storing any partition.
+///
+/// Input partitions are enumerated one at a time, for each distinct multi-set of
+/// part sums the output partitions are only counted, memoized over the
+/// remaining outputs and the remaining sums. Memory is therefore bounded by the
+/// number of such states instead of the number of mappings.
+pub fn count_mappings(inputs: &Set, outputs: &Set) -> MappingCounts {
+    MappingCounter::new(inputs, outputs, None).count()
+}
+
+/// Like `count_mappings`, but stops once `budget` is exhausted. Input partitions
+/// count towards the partition limit.
+pub fn count_mappings_with_budget(
+    inputs: &Set,
+    outputs: &Set,
+    budget: &SearchBudget,
+) -> MappingCounts {
+    MappingCounter::new(inputs, outputs, Some(budget)).count()
+}
+
+/// Output partition counts for one multi-set of input part sums.
+struct SumsCounts {
+    /// Distinct part sums in ascending order.
+    sums: Vec<u64>,
+    mappings: u64,
+    /// Indexed by output, then by position in `sums`: the number of output
+    /// partitions in which the part of that output has that sum.
+    linked: Vec<Vec<u64>>,
+}
+
+struct MappingCounter<'a> {
+    inputs: &'a Set,
+    outputs: &'a Set,
+    filter: SubsetSumsFilter<'a>,
+    budget: Option<&'a SearchBudget>,
+    cache: HashMap<Vec<u64>, SumsCounts>,
+    counts: MappingCounts,
+}
+
+impl<'a> MappingCounter<'a> {
+    fn new(
+        inputs: &'a Set,
+        outputs: &'a Set,
+        budget: Option<&'a SearchBudget>,
+    ) -> MappingCounter<'a> {
+        // Coins are identified by bits of a u64.
+        assert!(inputs.len() <= 64 && outputs.len() <= 64);
+        MappingCounter {
+            inputs,
+            outputs,
+            filter: match budget {
+                Some(budget) => SubsetSumsFilter::with_budget(outputs, budget),
+                None => SubsetSumsFilter::new(outputs),
+            },
+            budget,
+            cache: HashMap::new(),
+            counts: MappingCounts {
+                mappings: 0,
+                links: vec![vec![0; outputs.len()]; inputs.len()],
+            },
+        }
+    }
+
+    fn count(mut self) -> MappingCounts {
+        if !self.inputs.is_empty() {
+            let mut parts = Vec::new();
+            self.visit_input_partitions(full_mask(self.inputs.len()), &mut parts);
+        }
+        self.counts
+    }
+
+    /// Enumerates the partitions of the inputs in `remaining` whose parts all
+    /// match the subset sums filter of the outputs, like
+    /// `SumFilteredPartitionIterator`. Every partition is generated once by
+    /// always choosing the part of the lowest remaining input next.
+    fn visit_input_partitions(&mut self, remaining: u64, parts: &mut Vec<u64>) {
+        if self.exhausted() {
+            return;
+        }
+        if remaining == 0 {
+            if let Some(budget) = self.budget {
+                budget.record_partition();
+            }
+            self.add_input_partition(parts);
+            return;
+        }
+        let lowest = remaining & remaining.wrapping_neg();
+        for others in Submasks::new(remaining ^ lowest) {
+            let part = lowest | others;
+            if let Some(budget) = self.budget {
+                budget.record_filter_call();
+            }
+            if self.filter.contains(&mask_sum(self.inputs, part)) {
+                parts.push(part);
+                self.visit_input_partitions(remaining ^ part, parts);
+                parts.pop();
+            }
+        }
+    }
+
+    fn add_input_partition(&mut self, parts: &[u64]) {
+        let part_sums: Vec<u64> = parts.iter().map(|&p| mask_sum(self.inputs, p)).collect();
+        let mut key = part_sums.clone();
+        key.sort();
+        let outputs = self.outputs;
+        let sums_counts = self
+            .cache
+            .entry(key.clone())
+            .or_insert_with(|| count_output_partitions(outputs, &key));
+        if sums_counts.mappings == 0 {
+            return;
+        }
+        self.counts.mappings += sums_counts.mappings;
+        for (&part, part_sum) in parts.iter().zip(part_sums) {
+            let k = sums_counts
+                .sums
+                .binary_search(&part_sum)
+                .expect("Part sum is missing from its own sums");
+            for input in Bits::new(part) {
+                for (output, linked) in sums_counts.linked.iter().enumerate() {
+                    self.counts.links[input][output] += linked[k];
+                }
+            }
+        }
+    }
+
+    fn exhausted(&self) -> bool {
+        match self.budget {
+            Some(budget) => budget.exhausted(),
+            None => false,
+        }
+    }
+}
+
+/// Counts the partitions of `outputs` whose multi-set of part sums equals the
+/// sorted `part_sums`, in total and by the sum of the part of each output.
+fn count_output_partitions(outputs: &Set, part_sums: &[u64]) -> SumsCounts {
+    let mut sums: Vec<u64> = part_sums.to_vec();
+    sums.dedup();
+    let mut remaining_sums: Vec<u32> = sums
+        .iter()
+        .map(|s| part_sums.iter().filter(|&p| p == s).count() as u32)
+        .collect();
+    let mut counter = OutputPartitionCounter {
+        outputs,
+        sums: &sums,
+        memo: HashMap::new(),
+    };
+    let full = full_mask(outputs.len());
+    let mappings = counter.count(full, &mut remaining_sums);
+    let linked = (0..outputs.len())
+        .map(|output| {
+            let mut linked = vec![0u64; sums.len()];
+            if mappings == 0 {
+                return linked;
+            }
+            let own = 1u64 << output;
+            for others in Submasks::new(full ^ own) {
+                let part = own | others;
+                if let Ok(k) = sums.binary_search(&mask_sum(outputs, part)) {
+                    if remaining_sums[k] > 0 {
+                        remaining_sums[k] -= 1;
+                        linked[k] += counter.count(full ^ part, &mut remaining_sums);
+                        remaining_sums[k] += 1;
+                    }
+                }
+            }
+            linked
+        })
+        .collect();
+    SumsCounts {
+        sums,
+        mappings,
+        linked,
+    }
+}
+
+struct OutputPartitionCounter<'a> {
+    outputs: &'a Set,
+    sums: &'a [u64],
+    memo: HashMap<(u64, Vec<u32>), u64>,
+}
+
+impl<'a> OutputPartitionCounter<'a> {
+    /// The number of partitions of the outputs in `remaining` that use up
+    /// exactly the `remaining_sums`, given as counts per entry of `sums`.
+    fn count(&mut self, remaining: u64, remaining_sums: &mut Vec<u32>) -> u64 {
+        let required: u64 = self
+            .sums
+            .iter()
+            .zip(remaining_sums.iter())
+            .map(|(&sum, &count)| sum * count as u64)
+            .sum();
+        if mask_sum(self.outputs, remaining) != required {
+            return 0;
+        }
+        if remaining == 0 {
+            return 1;
+        }
+        let key = (remaining, remaining_sums.clone());
+        if let Some(&count) = self.memo.get(&key) {
+            return count;
+        }
+        let lowest = remaining & remaining.wrapping_neg();
+        let mut count = 0;
+        for others in Submasks::new(remaining ^ lowest) {
+            let part = lowest | others;
+            if let Ok(k) = self.sums.binary_search(&mask_sum(self.outputs, part)) {
+                if remaining_sums[k] > 0 {
+                    remaining_sums[k] -= 1;
+                    count += self.count(remaining ^ part, remaining_sums);
+                    remaining_sums[k] += 1;
+                }
+            }
+        }
+        self.memo.insert(key, count);
+        count
+    }
+}
+
+fn full_mask(len: usize) -> u64 {
+    match len {
+        64 => u64::MAX,
+        n => (1u64 << n) - 1,
+    }
+}
+
+fn mask_sum(set: &Set, mask: u64) -> u64 {
+    Bits::new(mask).map(|i| set[i]).sum()
+}
+
+/// Enumerates all subsets of a bit mask, including the mask itself and the
+/// empty set.
+struct Submasks {
+    mask: u64,
+    next: Option<u64>,
+}
+
+impl Submasks {
+    fn new(mask: u64) -> Submasks {
+        Submasks {
+            mask,
+            next: Some(mask),
+        }
+    }
+}
+
+impl Iterator for Submasks {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let current = self.next?;
+        self.next = match current {
+            0 => None,
+            _ => Some((current - 1) & self.mask),
+        };
+        Some(current)
+    }
+}
+
+/// Enumerates the indices of the bits set in a mask.
+struct Bits {
+    mask: u64,
+}
+
+impl Bits {
+    fn new(mask: u64) -> Bits {
+        Bits { mask }
+    }
+}
+
+impl Iterator for Bits {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.mask == 0 {
+            return None;
+        }
+        let index = self.mask.trailing_zeros() as usize;
+        self.mask &= self.mask - 1;
+        Some(index)
+    }
+}
@@ -0,0 +1,105 @@
+use serde_json;
+
+use super::*;
+use budget::{CancellationToken, SearchLimits, SearchStatus};
+use partition::SumFilteredPartitionIterator;
+use types::{Partition, Run};
+
+fn sorted_sums(partition: &Partition) -> Vec<u64> {
+    let mut sums: Vec<u64> = partition.iter().map(|set| set.iter().sum()).collect();
+    sums.sort();
+    sums
+}
+
+fn part_sum(partition: &Partition, coin: &u64) -> u64 {
+    partition
+        .iter()
+        .find(|set| set.contains(coin))
+        .expect("Coin is not in partition")
+        .iter()
+        .sum()
+}
+
+/// Materializes all mappings the slow way.
+fn brute_force(inputs: &Set, outputs: &Set) -> MappingCounts {
+    let in_partitions: Vec<Partition> =
+        SumFilteredPartitionIterator::new(inputs.clone(), &SubsetSumsFilter::new(outputs))
+            .collect();
+    let out_partitions: Vec<Partition> =
+        SumFilteredPartitionIterator::new(outputs.clone(), &SubsetSumsFilter::new(inputs))
+            .collect();
+    let mut counts = MappingCounts {
+        mappings: 0,
+        links: vec![vec![0; outputs.len()]; inputs.len()],
+    };
+    for in_partition in in_partitions.iter() {
+        for out_partition in out_partitions.iter() {
+            if sorted_sums(in_partition) != sorted_sums(out_partition) {
+                continue;
+            }
+            counts.mappings += 1;
+            for (i, in_coin) in inputs.iter().enumerate() {
+                for (o, out_coin) in outputs.iter().enumerate() {
+                    if part_sum(in_partition, in_coin) == part_sum(out_partition, out_coin) {
+                        counts.links[i][o] += 1;
+                    }
+                }
+            }
+        }
+    }
+    counts
+}
+
+#[test]
+fn test_count_mappings() {
+    assert_eq!(
+        count_mappings(&vec![1, 2], &vec![1, 2]),
+        MappingCounts {
+            mappings: 2,
+            links: vec![vec![2, 1], vec![1, 2]],
+        }
+    );
+    // Parts with equal sums: the outputs {2} and {2} can be matched to the
+    // inputs {1, 1} and {2} but this is still one pair of partitions.
+    assert_eq!(count_mappings(&vec![1, 1, 2], &vec![2, 2]).mappings, 2);
+    assert_eq!(count_mappings(&vec![5], &vec![3]).mappings, 0);
+    assert_eq!(
+        count_mappings(&vec![1, 3, 18], &vec![3, 4, 15]),
+        brute_force(&vec![1, 3, 18], &vec![3, 4, 15])
+    );
+}
+
+#[test]
+fn test_count_mappings_matches_enumeration() {
+    let test_files = vec![
+        include_str!("../partition/result-none-t-3-s-2-r-1.json"),
+        include_str!("../partition/result-output-t-3-s-2-r-1.json"),
+        include_str!("../partition/result-input-t-3-s-2-r-1.json"),
+    ];
+    for file in test_files {
+        let run: Run = serde_json::from_str(file).expect("Invalid json in input");
+        let counts = count_mappings(&run.in_coins, &run.out_coins);
+        assert!(counts.mappings > 0);
+        assert_eq!(counts, brute_force(&run.in_coins, &run.out_coins));
+    }
+}
+
+#[test]
+fn test_count_mappings_with_budget() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let budget = SearchBudget::new(SearchLimits::unlimited(), token);
+    let counts = count_mappings_with_budget(&vec![1, 2, 3], &vec![3, 3], &budget);
+    assert_eq!(counts.mappings, 0);
+    assert_eq!(budget.status(), SearchStatus::Cancelled);
+}
+
+#[test]
+fn test_submasks() {
+    assert_eq!(Submasks::new(0).collect::<Vec<u64>>(), vec![0]);
+    assert_eq!(
+        Submasks::new(0b101).collect::<Vec<u64>>(),
+        vec![0b101, 0b100, 0b001, 0]
+    );
+    assert_eq!(Bits::new(0b1010).collect::<Vec<usize>>(), vec![1, 3]);
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+
+use blockchain::{hash_to_hex, Block, BlockLocation};
+use distribution::Distribution;
+use shapes::ShapeHistogram;
+use utxo::UtxoSet;
+
+#[cfg(test)]
+mod test;
+
+/// The raw coin counts a distribution is built from. Unlike a `Distribution`
+/// they can be updated with new blocks and merged with the counts of other
+/// blocks, since they record which block heights they cover.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CoinCounts {
+    pub bucket_size: u64,
+    /// Larger coins are not counted.
+    pub max_coin_value: u64,
+    /// Coins that are a multiple of this unit are also counted exactly, to find
+    /// the point masses of round amounts.
+    pub point_mass_unit: u64,
+    /// The number of coins of each bucket, keyed by its smallest amount.
+    pub buckets: BTreeMap<u64, u64>,
+    /// The number of coins with exactly this amount.
+    pub exact: BTreeMap<u64, u64>,
+    /// The counted block heights as sorted, disjoint and inclusive ranges.
+    pub heights: Vec<(u64, u64)>,
+    /// Set if only the coins unspent at this height are counted, instead of
+    /// all outputs of the covered heights. Such counts can't be updated or
+    /// merged.
+    #[serde(default)]
+    pub unspent_at: Option<u64>,
+    /// The numbers of inputs and outputs of the transactions of the covered
+    /// heights. `None` in counts written before shapes were collected, whose
+    /// shapes can't be completed by updating them.
+    #[serde(default)]
+    pub shapes: Option<ShapeHistogram>,
+    /// The highest counted height and the hash of its block. If that block is
+    /// still in the main chain, so are all counted blocks below it. `None` in
+    /// counts written before it was recorded.
+    #[serde(default)]
+    pub tip: Option<(u64, String)>,
+}
+
+impl CoinCounts {
+    pub fn new(bucket_size: u64, max_coin_value: u64, point_mass_unit: u64) -> CoinCounts {
+        assert!(
+            bucket_size > 0 && point_mass_unit > 0,
+            "Bucket size and point mass unit must be positive"
+        );
+        CoinCounts {
+            bucket_size,
+            max_coin_value,
+            point_mass_unit,
+            buckets: BTreeMap::new(),
+            exact: BTreeMap::new(),
+            heights: Vec::new(),
+            unspent_at: None,
+            shapes: Some(ShapeHistogram::new()),
+            tip: None,
+        }
+    }
+
+    /// Records the hash of the counted block at `height`, if no higher block
+    /// is recorded yet.
+    pub fn record_tip(&mut self, height: u64, hash: &[u8; 32]) {
+        if self.tip.as_ref().map_or(true, |&(tip, _)| tip <= height) {
+            self.tip = Some((height, hash_to_hex(hash)));
+        }
+    }
+
+    /// Fails if the recorded tip is not the block at its height in `chain`,
+    /// the main chain ordered by height, e.g. after a reorganization.
+    pub fn check_tip(&self, chain: &[(usize, BlockLocation)]) -> Result<(), String> {
+        let &(height, ref hash) = match self.tip {
+            Some(ref tip) => tip,
+            None => return Ok(()),
+        };
+        match chain.get(height as usize) {
+            Some((_, location)) if hash_to_hex(&location.hash) == *hash => Ok(()),
+            _ => Err(format!(
+                "The counted block {} at height {} is not in the main chain anymore",
+                hash, height
+            )),
+        }
+    }
+
+    /// Counts the coins of `utxos`, the coins unspent at `height`.
+    pub fn add_unspent(&mut self, utxos: &UtxoSet, height: u64) {
+        assert!(
+            self.heights.is_empty(),
+            "Only empty counts can count unspent coins"
+        );
+        for value in utxos.values() {
+            self.add_coin(value);
+        }
+        self.heights = vec![(0, height)];
+        self.unspent_at = Some(height);
+    }
+
+    pub fn covers(&self, height: u64) -> bool {
+        self.heights
+            .iter()
+            .any(|&(first, last)| first <= height && height <= last)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.buckets.values().sum()
+    }
+
+    pub fn add_coin(&mut self, value: u64) {
+        if value > self.max_coin_value {
+            return;
+        }
+        *self
+            .buckets
+            .entry(value / self.bucket_size * self.bucket_size)
+            .or_insert(0) += 1;
+        if value > 0 && value % self.point_mass_unit == 0 {
+            *self.exact.entry(value).or_insert(0) += 1;
+        }
+    }
+
+    /// Counts the outputs of the block at `height`, unless that height is
+    /// already covered.
+    pub fn add_block(&mut self, height: u64, block: &Block) -> bool {
+        assert!(
+            self.unspent_at.is_none(),
+            "Counts of unspent coins can't be updated"
+        );
+        if self.covers(height) {
+            return false;
+        }
+        for transaction in block.transactions.iter() {
+            for output in transaction.outputs.iter() {
+                if output.value >= 0 {
+                    self.add_coin(output.value as u64);
+                }
+            }
+        }
+        if let Some(shapes) = self.shapes.as_mut() {
+            shapes.add_block(block);
+        }
+        self.add_heights(height, height);
+        true
+    }
+
+    /// Adds the counts of other blocks, which fails if they were counted with
+    /// different parameters or cover some of the same heights.
+    pub fn merge(&mut self, other: &CoinCounts) -> Result<(), String> {
+        if (self.bucket_size, self.max_coin_value, self.point_mass_unit)
+            != (
+                other.bucket_size,
+                other.max_coin_value,
+                other.point_mass_unit,
+            )
+        {
+            return Err("The counts were built with different parameters".to_string());
+        }
+        if self.unspent_at.is_some() || other.unspent_at.is_some() {
+            return Err("Counts of unspent coins can't be merged".to_string());
+        }
+        for &(first, last) in other.heights.iter() {
+            if self
+                .heights
+                .iter()
+                .any(|&(other_first, other_last)| first <= other_last && other_first <= last)
+            {
+                return Err(format!(
+                    "Both counts cover some of the heights {} to {}",
+                    first, last
+                ));
+            }
+        }
+        for (bucket, count) in other.buckets.iter() {
+            *self.buckets.entry(*bucket).or_insert(0) += count;
+        }
+        for (value, count) in other.exact.iter() {
+            *self.exact.entry(*value).or_insert(0) += count;
+        }
+        match (self.shapes.as_mut(), other.shapes.as_ref()) {
+            (Some(shapes), Some(other_shapes)) => shapes.merge(other_shapes),
+            _ => self.shapes = None,
+        }
+        if let Some((height, ref hash)) = other.tip {
+            if self.tip.as_ref().map_or(true, |&(tip, _)| tip < height) {
+                self.tip = Some((height, hash.clone()));
+            }
+        }
+        for &(first, last) in other.heights.iter() {
+            self.add_heights(first, last);
+        }
+        Ok(())
+    }
+
+    fn add_heights(&mut self, first: u64, last: u64) {
+        self.heights.push((first, last));
+        self.heights.sort();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.heights.len());
+        for &(first, last) in self.heights.iter() {
+            match merged.last_mut() {
+                Some(previous) if first <= previous.1.saturating_add(1) => {
+                    previous.1 = previous.1.max(last)
+                }
+                _ => merged.push((first, last)),
+            }
+        }
+        self.heights = merged;
+    }
+
+    /// The normalized cumulative distribution of the counted coins. Amounts
+    /// with at least `min_point_mass` of all coins that hold most coins of
+    /// their bucket are recorded as point masses.
+    pub fn to_distribution(&self, min_point_mass: f64) -> Distribution {
+        let total = self.total() as f64;
+        let point_masses = self
+            .exact
+            .iter()
+            .filter(|&(value, &count)| {
+                let bucket = self.buckets[&(value / self.bucket_size * self.bucket_size)];
+                count as f64 / total >= min_point_mass && count * 2 > bucket
+            })
+            .map(|(value, &count)| (*value, count as f64 / total))
+            .collect();
+        let mut cumulative = 0u64;
+        let cumulative_normalized = self
+            .buckets
+            .iter()
+            .map(|(bucket, count)| {
+                cumulative += count;
+                (*bucket, cumulative as f64 / total)
+            })
+            .collect();
+        Distribution::with_point_masses(cumulative_normalized, point_masses)
+    }
+}
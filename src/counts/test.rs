@@ -0,0 +1,106 @@
+use super::*;
+
+fn counts(coins: &[u64], first: u64, last: u64) -> CoinCounts {
+    let mut counts = CoinCounts::new(100, 1_000_000, 1_000);
+    for &coin in coins {
+        counts.add_coin(coin);
+    }
+    counts.heights = vec![(first, last)];
+    counts
+}
+
+#[test]
+fn test_add_coin() {
+    let counts = counts(&[0, 150, 199, 1_000, 1_000, 2_000_000], 0, 0);
+    assert_eq!(
+        counts.buckets.iter().collect::<Vec<_>>(),
+        vec![(&0, &1), (&100, &2), (&1_000, &2)]
+    );
+    assert_eq!(counts.exact.iter().collect::<Vec<_>>(), vec![(&1_000, &2)]);
+    assert_eq!(counts.total(), 5);
+}
+
+#[test]
+fn test_merge() {
+    let mut first = counts(&[150, 1_000], 0, 9);
+    first.merge(&counts(&[1_000], 10, 19)).unwrap();
+    first.merge(&counts(&[120], 30, 39)).unwrap();
+    assert_eq!(first.heights, vec![(0, 19), (30, 39)]);
+    assert_eq!(first.buckets[&100], 2);
+    assert_eq!(first.exact[&1_000], 2);
+    assert!(first.covers(15) && !first.covers(20));
+    assert!(first.merge(&counts(&[1], 19, 25)).is_err());
+    assert!(first.merge(&CoinCounts::new(10, 1_000_000, 1_000)).is_err());
+    assert_eq!(first.total(), 4);
+    assert!(first.shapes.is_some());
+    // Counts written before shapes were collected have none to merge.
+    let mut old = counts(&[1], 40, 49);
+    old.shapes = None;
+    first.merge(&old).unwrap();
+    assert!(first.shapes.is_none());
+}
+
+#[test]
+fn test_tip() {
+    let location = |hash: u8| BlockLocation {
+        hash: [hash; 32],
+        previous_block_header_hash: [0; 32],
+        offset: 0,
+    };
+    let chain = vec![(0, location(1)), (0, location(2))];
+    let mut first = counts(&[150], 0, 0);
+    assert!(first.check_tip(&chain).is_ok());
+    first.record_tip(0, &[1; 32]);
+    assert!(first.check_tip(&chain).is_ok());
+    // The block at height 1 was replaced.
+    let mut second = counts(&[150], 1, 1);
+    second.record_tip(1, &[3; 32]);
+    assert!(second.check_tip(&chain).is_err());
+    first.merge(&second).unwrap();
+    assert_eq!(first.tip, Some((1, hash_to_hex(&[3; 32]))));
+    assert!(first.check_tip(&chain[..1]).is_err());
+}
+
+#[test]
+fn test_add_block_heights() {
+    let block = Block {
+        header: ::blockchain::parse_block_header(&[0u8; 80])
+            .to_full_result()
+            .unwrap(),
+        transactions: Vec::new(),
+    };
+    let mut counts = CoinCounts::new(100, 1_000_000, 1_000);
+    assert!(counts.add_block(2, &block));
+    assert!(counts.add_block(0, &block));
+    assert!(counts.add_block(1, &block));
+    assert!(!counts.add_block(1, &block));
+    assert_eq!(counts.heights, vec![(0, 2)]);
+}
+
+#[test]
+fn test_to_distribution() {
+    // 1000 holds most coins of its bucket, 2000 does not.
+    let counts = counts(
+        &[150, 1_000, 1_000, 1_000, 1_050, 2_000, 2_010, 2_020],
+        0,
+        0,
+    );
+    let distribution = counts.to_distribution(0.1);
+    assert_eq!(
+        distribution.cumulative_normalized,
+        vec![(100, 0.125), (1_000, 0.625), (2_000, 1.0)]
+    );
+    assert_eq!(distribution.point_masses, vec![(1_000, 0.375)]);
+    assert!(counts.to_distribution(0.5).point_masses.is_empty());
+}
+
+#[test]
+fn test_add_unspent() {
+    let mut counts = CoinCounts::new(100, 1_000_000, 1_000);
+    counts.add_unspent(&UtxoSet::new(), 7);
+    assert_eq!(counts.unspent_at, Some(7));
+    assert_eq!(counts.heights, vec![(0, 7)]);
+    let mut other = CoinCounts::new(100, 1_000_000, 1_000);
+    other.heights = vec![(8, 9)];
+    assert!(other.merge(&counts).is_err());
+}
@@ -0,0 +1,110 @@
+use std::cmp::Reverse;
+use std::mem;
+
+use types::{Partition, Set};
+
+#[cfg(test)]
+mod test;
+
+/// Removes the mappings that are derived from another mapping by merging some
+/// of its sub-transactions, only keeping the finest interpretations of a
+/// transaction. The remaining mappings keep their order.
+///
+/// A mapping is derived if another mapping with more parts refines both its
+/// input and its output partition. Since refinement is transitive, each
+/// mapping only has to be compared to the non-derived mappings with more
+/// parts, so mappings are visited from the finest to the coarsest. Matching
+/// only the sums of the output sets is not enough, as coarser mappings may
+/// pair the coins differently.
+pub fn filter_derived_partitions(
+    partitions: &[(Partition, Partition)],
+) -> Vec<(Partition, Partition)> {
+    let mut by_size: Vec<usize> = (0..partitions.len()).collect();
+    by_size.sort_by_key(|&index| Reverse(partitions[index].0.len()));
+    let mut non_derived: Vec<usize> = Vec::new();
+    for index in by_size {
+        let mapping = &partitions[index];
+        let derived = non_derived.iter().any(|&finer| {
+            partitions[finer].0.len() > mapping.0.len() && is_derived(mapping, &partitions[finer])
+        });
+        if !derived {
+            non_derived.push(index);
+        }
+    }
+    non_derived.sort();
+    non_derived
+        .into_iter()
+        .map(|index| partitions[index].clone())
+        .collect()
+}
+
+/// Whether `part` merges sub-transactions of `finer_part`, i.e. whether the
+/// input and the output partition of `finer_part` refine those of `part`.
+fn is_derived(part: &(Partition, Partition), finer_part: &(Partition, Partition)) -> bool {
+    refines(&finer_part.0, &part.0) && refines(&finer_part.1, &part.1)
+}
+
+/// Whether every part of `finer` is contained in a part of `coarser`, so that
+/// the parts of `coarser` are unions of parts of `finer`. Partitions only hold
+/// coin values, so with duplicate values parts are assigned by backtracking.
+fn refines(finer: &Partition, coarser: &Partition) -> bool {
+    if finer.len() < coarser.len() {
+        return false;
+    }
+    let mut remaining: Vec<Set> = coarser
+        .iter()
+        .map(|set| {
+            let mut set = set.clone();
+            set.sort();
+            set
+        })
+        .collect();
+    let mut parts: Vec<Set> = finer
+        .iter()
+        .map(|set| {
+            let mut set = set.clone();
+            set.sort();
+            set
+        })
+        .collect();
+    // Placing large parts first prunes the search early.
+    parts.sort_by_key(|part| Reverse(part.len()));
+    assign_parts(&parts, &mut remaining)
+}
+
+fn assign_parts(parts: &[Set], remaining: &mut Vec<Set>) -> bool {
+    let part = match parts.first() {
+        Some(part) => part,
+        None => return remaining.iter().all(|set| set.is_empty()),
+    };
+    for index in 0..remaining.len() {
+        if let Some(rest) = remove_all(&remaining[index], part) {
+            let previous = mem::replace(&mut remaining[index], rest);
+            if assign_parts(&parts[1..], remaining) {
+                return true;
+            }
+            remaining[index] = previous;
+        }
+    }
+    false
+}
+
+/// Removes the sorted multi-set `part` from the sorted multi-set `set`, if it
+/// is contained in it.
+fn remove_all(set: &Set, part: &Set) -> Option<Set> {
+    let mut rest = Vec::with_capacity(set.len());
+    let mut part = part.iter().peekable();
+    for coin in set {
+        match part.peek() {
+            Some(&&next) if next == *coin => {
+                part.next();
+            }
+            Some(&&next) if next < *coin => return None,
+            _ => rest.push(*coin),
+        }
+    }
+    match part.next() {
+        Some(_) => None,
+        None => Some(rest),
+    }
+}
@@ -0,0 +1,112 @@
+use serde_json;
+
+use super::*;
+use types::Run;
+
+#[test]
+fn test_is_derived() {
+    assert!(is_derived(
+        &(
+            vec![vec![1, 2, 3], vec![3, 4]],
+            vec![vec![1, 2, 3], vec![3, 4]]
+        ),
+        &(
+            vec![vec![1, 2], vec![3], vec![3, 4]],
+            vec![vec![1, 2], vec![3], vec![3, 4]]
+        )
+    ));
+    assert!(is_derived(
+        &(
+            vec![vec![2, 2, 3], vec![3, 4]],
+            vec![vec![2, 2, 3], vec![3, 4]]
+        ),
+        &(
+            vec![vec![3, 2], vec![2], vec![3, 4]],
+            vec![vec![3, 2], vec![2], vec![3, 4]]
+        )
+    ))
+}
+
+#[test]
+fn test_refines() {
+    assert!(refines(
+        &vec![vec![1], vec![2], vec![3]],
+        &vec![vec![1, 3], vec![2]]
+    ));
+    assert!(!refines(
+        &vec![vec![1, 2], vec![3]],
+        &vec![vec![1, 3], vec![2]]
+    ));
+    // The part {2} has to go to the second 2 of {2, 2} or to {2, 5}.
+    assert!(refines(
+        &vec![vec![2], vec![2], vec![2], vec![5]],
+        &vec![vec![2, 2], vec![2, 5]]
+    ));
+    assert!(!refines(&vec![vec![2], vec![2]], &vec![vec![2, 3]]));
+}
+
+#[test]
+fn test_filter_derived_partitions() {
+    assert!(filter_derived_partitions(&[]).is_empty());
+    // All mappings belong to the transaction with inputs and outputs [1, 2, 3, 4].
+    let finest = (
+        vec![vec![1], vec![2], vec![3], vec![4]],
+        vec![vec![1], vec![2], vec![3], vec![4]],
+    );
+    let merged = (vec![vec![1, 2, 3, 4]], vec![vec![1, 2, 3, 4]]);
+    // Only derived by merging three levels at once.
+    assert_eq!(
+        filter_derived_partitions(&[merged.clone(), finest.clone()]),
+        vec![finest.clone()]
+    );
+    let three = (
+        vec![vec![1], vec![2], vec![3, 4]],
+        vec![vec![1], vec![2], vec![3, 4]],
+    );
+    // Merging the outputs 1 and 2 of `three` gives the output sums 3 and 7,
+    // which used to be enough to count as derived. But here the inputs 1 and
+    // 2 pay for the output 3, which is no merge of sub-transactions of
+    // `three`, so both mappings are kept.
+    let crossed = (vec![vec![1, 2], vec![3, 4]], vec![vec![3], vec![1, 2, 4]]);
+    assert_eq!(
+        filter_derived_partitions(&[three.clone(), crossed.clone()]),
+        vec![three.clone(), crossed.clone()]
+    );
+    // Both are merges of sub-transactions of `finest`.
+    assert_eq!(
+        filter_derived_partitions(&[three, finest.clone(), crossed]),
+        vec![finest]
+    );
+}
+
+#[test]
+fn test_filter_derived_partitions_fixtures() {
+    // The number of mappings and the sizes of the non-derived ones.
+    let test_files = vec![
+        (
+            include_str!("../partition/result-none-t-3-s-2-r-1.json"),
+            8,
+            vec![3, 3],
+        ),
+        (
+            include_str!("../partition/result-output-t-3-s-2-r-1.json"),
+            9,
+            vec![2, 3, 3],
+        ),
+        (
+            include_str!("../partition/result-input-t-3-s-2-r-1.json"),
+            13,
+            vec![3, 3, 3, 3],
+        ),
+    ];
+    for (file, mappings, sizes) in test_files {
+        let run: Run = serde_json::from_str(file).expect("Invalid json in input");
+        assert_eq!(run.partition_tuples.len(), mappings);
+        let mut non_derived: Vec<usize> = filter_derived_partitions(&run.partition_tuples)
+            .iter()
+            .map(|mapping| mapping.0.len())
+            .collect();
+        non_derived.sort();
+        assert_eq!(non_derived, sizes);
+    }
+}
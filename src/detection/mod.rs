@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use blockchain::{hash_to_hex, Transaction};
+
+#[cfg(test)]
+mod test;
+
+/// The pool denominations of Samourai Whirlpool in satoshis.
+pub const WHIRLPOOL_DENOMINATIONS: [u64; 4] = [100_000, 1_000_000, 5_000_000, 50_000_000];
+
+/// The heuristic a likely CoinJoin or PayJoin transaction was detected by.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Heuristic {
+    /// Five inputs and five outputs of a Whirlpool pool denomination.
+    Whirlpool,
+    /// Many equal outputs of about 0.1 BTC like Wasabi 1 rounds, or many
+    /// inputs and outputs mostly of the standard denominations of Wasabi 2.
+    Wasabi,
+    /// At least three equal outputs and at most one change output per equal
+    /// output, like the makers and the taker of JoinMarket.
+    JoinMarket,
+    /// At least two equal outputs and as many inputs.
+    EqualOutputs,
+    /// Two outputs and an input that neither output needs as payment, which
+    /// hints that the receiver contributed an input.
+    PayJoin,
+}
+
+impl fmt::Display for Heuristic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Heuristic::Whirlpool => "whirlpool",
+            Heuristic::Wasabi => "wasabi",
+            Heuristic::JoinMarket => "join_market",
+            Heuristic::EqualOutputs => "equal_outputs",
+            Heuristic::PayJoin => "pay_join",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Heuristic {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Heuristic, String> {
+        match name {
+            "whirlpool" => Ok(Heuristic::Whirlpool),
+            "wasabi" => Ok(Heuristic::Wasabi),
+            "join_market" => Ok(Heuristic::JoinMarket),
+            "equal_outputs" => Ok(Heuristic::EqualOutputs),
+            "pay_join" => Ok(Heuristic::PayJoin),
+            _ => Err(format!("Unknown heuristic {}", name)),
+        }
+    }
+}
+
+/// A transaction flagged by `detect`, as written by `detect_coinjoins`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Candidate {
+    /// The transaction id as shown by block explorers.
+    pub txid: String,
+    pub height: u64,
+    pub heuristic: Heuristic,
+    pub inputs: usize,
+    pub outputs: usize,
+    /// The number of outputs of the most common value.
+    pub equal_outputs: usize,
+    /// The most common output value, 0 if all values differ.
+    pub denomination: u64,
+}
+
+impl Candidate {
+    pub fn new(transaction: &Transaction, height: u64, heuristic: Heuristic) -> Candidate {
+        let (denomination, equal_outputs) = most_common_output(&output_values(transaction));
+        Candidate {
+            txid: hash_to_hex(&transaction.txid),
+            height,
+            heuristic,
+            inputs: transaction.inputs.len(),
+            outputs: transaction.outputs.len(),
+            equal_outputs,
+            denomination,
+        }
+    }
+}
+
+fn output_values(transaction: &Transaction) -> Vec<u64> {
+    transaction
+        .outputs
+        .iter()
+        .map(|output| output.value.max(0) as u64)
+        .collect()
+}
+
+/// The most common positive output value and how many outputs have it. Ties
+/// go to the larger value.
+fn most_common_output(values: &[u64]) -> (u64, usize) {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for &value in values.iter().filter(|&&value| value > 0) {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(value, count)| (count, value))
+        .max()
+        .filter(|&(count, _)| count > 1)
+        .map_or((0, 0), |(count, value)| (value, count))
+}
+
+/// Whether `value` is one of the standard denominations of Wasabi 2: powers
+/// of two and three, two times powers of three and one, two and five times
+/// powers of ten, of at least 5000 satoshis.
+pub fn is_wasabi2_denomination(value: u64) -> bool {
+    if value < 5000 {
+        return false;
+    }
+    let is_power = |mut value: u64, base: u64| {
+        while value % base == 0 {
+            value /= base;
+        }
+        value == 1
+    };
+    is_power(value, 2)
+        || is_power(value, 3)
+        || (value % 2 == 0 && is_power(value / 2, 3))
+        || is_power(value, 10)
+        || (value % 2 == 0 && is_power(value / 2, 10))
+        || (value % 5 == 0 && is_power(value / 5, 10))
+}
+
+/// Checks a transaction against the CoinJoin heuristics, most specific
+/// first, and then against the PayJoin heuristic, which needs the values of
+/// the spent coins. Coinbase transactions are never flagged.
+pub fn detect(transaction: &Transaction, input_values: Option<&[u64]>) -> Option<Heuristic> {
+    if transaction.inputs.iter().any(|input| input.is_coinbase()) {
+        return None;
+    }
+    let inputs = transaction.inputs.len();
+    let values = output_values(transaction);
+    let (denomination, equal) = most_common_output(&values);
+
+    if inputs == 5
+        && values.len() == 5
+        && equal == 5
+        && WHIRLPOOL_DENOMINATIONS.contains(&denomination)
+    {
+        return Some(Heuristic::Whirlpool);
+    }
+    if equal >= 10 && inputs >= equal && (9_500_000..=10_500_000).contains(&denomination) {
+        return Some(Heuristic::Wasabi);
+    }
+    if inputs >= 50
+        && values.len() >= 50
+        && values
+            .iter()
+            .filter(|&&value| is_wasabi2_denomination(value))
+            .count()
+            * 2
+            >= values.len()
+    {
+        return Some(Heuristic::Wasabi);
+    }
+    if equal >= 3 && inputs >= equal && values.len() <= 2 * equal {
+        return Some(Heuristic::JoinMarket);
+    }
+    if equal >= 2 && inputs >= equal {
+        return Some(Heuristic::EqualOutputs);
+    }
+
+    let input_values = input_values?;
+    if input_values.len() >= 2 && values.len() == 2 && equal == 0 {
+        let total: u64 = input_values.iter().sum();
+        let fee = total.saturating_sub(values.iter().sum());
+        let smallest = *input_values.iter().min().unwrap();
+        let largest = *values.iter().max().unwrap();
+        // Even the larger output could be paid, with the fee, without the
+        // smallest input, so a wallet paying either output alone would not
+        // have added it.
+        if total - smallest >= largest + fee {
+            return Some(Heuristic::PayJoin);
+        }
+    }
+    None
+}
@@ -0,0 +1,134 @@
+use super::*;
+use blockchain::{Outpoint, TransactionInput, TransactionOutput};
+use serde_json;
+
+fn transaction(inputs: usize, outputs: &[u64]) -> Transaction {
+    Transaction {
+        txid: [0; 32],
+        version: 1,
+        lock_time: 0,
+        inputs: (0..inputs)
+            .map(|index| TransactionInput {
+                previous_output: Outpoint {
+                    hash: [1; 32],
+                    index: index as u32,
+                },
+                sequence: 0,
+                script: Vec::new(),
+                witness: Vec::new(),
+            })
+            .collect(),
+        outputs: outputs
+            .iter()
+            .map(|&value| TransactionOutput {
+                value: value as i64,
+                pk_script: vec![0x76],
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_whirlpool() {
+    let tx = transaction(5, &[1_000_000; 5]);
+    assert_eq!(detect(&tx, None), Some(Heuristic::Whirlpool));
+    let tx = transaction(5, &[1_200_000; 5]);
+    assert_eq!(detect(&tx, None), Some(Heuristic::JoinMarket));
+}
+
+#[test]
+fn test_wasabi() {
+    let mut outputs = vec![9_990_000; 12];
+    outputs.extend_from_slice(&[123_456, 234_567, 345_678]);
+    assert_eq!(
+        detect(&transaction(14, &outputs), None),
+        Some(Heuristic::Wasabi)
+    );
+
+    let standard = [
+        8192, 10_000, 20_000, 50_000, 59_049, 65_536, 100_000, 118_098,
+    ];
+    let mut outputs: Vec<u64> = (0..40).map(|i| standard[i % 8]).collect();
+    outputs.extend((0..20).map(|i| 123_457 + i));
+    assert_eq!(
+        detect(&transaction(60, &outputs), None),
+        Some(Heuristic::Wasabi)
+    );
+    assert!(is_wasabi2_denomination(8192));
+    assert!(is_wasabi2_denomination(2 * 59049));
+    assert!(is_wasabi2_denomination(50_000));
+    assert!(!is_wasabi2_denomination(4096));
+    assert!(!is_wasabi2_denomination(123_456));
+}
+
+#[test]
+fn test_join_market_and_equal_outputs() {
+    let tx = transaction(4, &[500_000, 500_000, 500_000, 1_234, 5_678]);
+    assert_eq!(detect(&tx, None), Some(Heuristic::JoinMarket));
+    let tx = transaction(4, &[500_000, 500_000, 500_000, 1, 2, 3, 4]);
+    assert_eq!(detect(&tx, None), Some(Heuristic::EqualOutputs));
+    let tx = transaction(2, &[500_000, 500_000, 1_234]);
+    assert_eq!(detect(&tx, None), Some(Heuristic::EqualOutputs));
+    let tx = transaction(1, &[500_000, 500_000]);
+    assert_eq!(detect(&tx, None), None);
+    let tx = transaction(3, &[0, 0, 0, 1_234]);
+    assert_eq!(detect(&tx, None), None);
+}
+
+#[test]
+fn test_pay_join() {
+    // The sender pays 30000 from 100000, the receiver adds 50000.
+    let tx = transaction(2, &[69_000, 80_000]);
+    assert_eq!(detect(&tx, None), None);
+    assert_eq!(
+        detect(&tx, Some(&[100_000, 50_000])),
+        Some(Heuristic::PayJoin)
+    );
+    // Both inputs are needed for the larger output.
+    let tx = transaction(2, &[140_000, 9_000]);
+    assert_eq!(detect(&tx, Some(&[100_000, 50_000])), None);
+}
+
+#[test]
+fn test_coinbase() {
+    let mut tx = transaction(1, &[1_000, 1_000, 1_000]);
+    tx.inputs[0].previous_output = Outpoint {
+        hash: [0; 32],
+        index: 0xffff_ffff,
+    };
+    assert_eq!(detect(&tx, None), None);
+}
+
+#[test]
+fn test_candidate() {
+    let tx = transaction(4, &[500_000, 1_234, 500_000, 500_000]);
+    let candidate = Candidate::new(&tx, 7, Heuristic::JoinMarket);
+    assert_eq!(candidate.txid, "0".repeat(64));
+    assert_eq!(
+        (candidate.inputs, candidate.outputs, candidate.equal_outputs),
+        (4, 4, 3)
+    );
+    assert_eq!(candidate.denomination, 500_000);
+    assert_eq!(
+        serde_json::to_value(&candidate).unwrap()["heuristic"],
+        "join_market"
+    );
+}
+
+#[test]
+fn test_heuristic_names() {
+    for heuristic in [
+        Heuristic::Whirlpool,
+        Heuristic::Wasabi,
+        Heuristic::JoinMarket,
+        Heuristic::EqualOutputs,
+        Heuristic::PayJoin,
+    ]
+    .iter()
+    {
+        let name = heuristic.to_string();
+        assert_eq!(name.parse::<Heuristic>(), Ok(*heuristic));
+        assert_eq!(serde_json::to_value(heuristic).unwrap(), name);
+    }
+    assert!("coinjoin".parse::<Heuristic>().is_err());
+}
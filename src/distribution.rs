@@ -1,149 +1,66 @@
-use rand::{random, thread_rng, Open01, Rng};
-use types::{Set, Transaction};
+use rand::{random, Open01};
+use rmp_serde;
+use sampler::CoinSampler;
+use serde::de::DeserializeOwned;
+use serde_json;
+use shapes::ShapeHistogram;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read};
 
 #[derive(Serialize, Deserialize)]
 pub struct Distribution {
     pub cumulative_normalized: Vec<(u64, f64)>,
-}
-
-fn realize_subsum(v: &Vec<u64>, sum: u64) -> Vec<u64> {
-    let mut d = sum;
-    v.iter()
-        .flat_map(|&o| {
-            if d == 0 {
-                vec![o]
-            } else if o <= d {
-                d -= o;
-                vec![o]
-            } else if o > d {
-                let r = vec![o - d, d];
-                d = 0;
-                r
-            } else {
-                panic!("Universe is collapsing")
-            }
-        })
-        .collect()
+    /// Amounts that occur so often that they stand out of their bucket, e.g.
+    /// round amounts, with the share of all coins that have exactly this
+    /// amount. This mass is also part of `cumulative_normalized`. Empty for
+    /// distributions built before point masses were recorded.
+    #[serde(default)]
+    pub point_masses: Vec<(u64, f64)>,
 }
 
 impl Distribution {
     pub fn new(cumulative_normalized: Vec<(u64, f64)>) -> Distribution {
-        Distribution {
-            cumulative_normalized,
-        }
-    }
-
-    pub fn random_coinjoin_transaction(
-        &self,
-        num_transactions: u64,
-        transaction_size: u64,
-    ) -> (Vec<Transaction>, Set, Set) {
-        let mut transactions: Vec<Transaction> = Vec::new();
-        let mut in_coins: Set = self.random_set(transaction_size);
-        let mut out_coins: Set = self.output_pair(&in_coins);
-        transactions.push(Transaction::new(in_coins.clone(), out_coins.clone()));
-        for _ in 1..num_transactions {
-            let mut new_in = self.random_set(transaction_size);
-            let mut new_out = self.output_pair(&new_in);
-            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
-            in_coins.append(&mut new_in);
-            out_coins.append(&mut new_out);
-        }
-        (transactions, in_coins, out_coins)
+        Distribution::with_point_masses(cumulative_normalized, Vec::new())
     }
 
-    pub fn random_coinjoin_transaction_shuffled(
-        &self,
-        num_transactions: u64,
-        transaction_size: u64,
-    ) -> (Vec<Transaction>, Set, Set) {
-        let mut transactions: Vec<Transaction> = Vec::new();
-        let mut in_coins: Set = self.random_set(transaction_size);
-        let mut out_coins: Set = self.output_pair(&in_coins);
-        transactions.push(Transaction::new(in_coins.clone(), out_coins.clone()));
-        for _ in 1..num_transactions {
-            let mut new_in = self.random_set(transaction_size);
-            let mut new_out = self.output_pair(&new_in);
-            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
-            let diff: i64 =
-                new_out.iter().sum::<u64>() as i64 - out_coins.iter().sum::<u64>() as i64;
-            if diff > 0 {
-                new_out = realize_subsum(&new_out, diff as u64)
-            } else if diff < 0 {
-                out_coins = realize_subsum(&out_coins, -diff as u64)
-            };
-            in_coins.append(&mut new_in);
-            out_coins.append(&mut new_out);
+    pub fn with_point_masses(
+        cumulative_normalized: Vec<(u64, f64)>,
+        point_masses: Vec<(u64, f64)>,
+    ) -> Distribution {
+        Distribution {
+            cumulative_normalized,
+            point_masses,
         }
-        (transactions, in_coins, out_coins)
     }
+}
 
-    pub fn random_coinjoin_transaction_input_shuffled(
-        &self,
-        num_transactions: u64,
-        transaction_size: u64,
-    ) -> (Vec<Transaction>, Set, Set) {
-        let mut transactions: Vec<Transaction> = Vec::new();
-        let mut in_coins: Set = self.random_set(transaction_size);
-        let mut out_coins: Set = self.output_pair(&in_coins);
-        transactions.push(Transaction::new(in_coins.clone(), out_coins.clone()));
-        for _ in 1..num_transactions {
-            let mut new_in = self.random_set(transaction_size);
-            let mut new_out = self.output_pair(&new_in);
-            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
-            in_coins.append(&mut new_in);
-            thread_rng().shuffle(&mut in_coins);
-            let mut random_in_sum: u64 = in_coins.iter().take(transaction_size as usize).sum();
-            while random_in_sum >= new_out.iter().sum() && random_in_sum >= out_coins.iter().sum() {
-                thread_rng().shuffle(&mut in_coins);
-                random_in_sum = in_coins.iter().take(transaction_size as usize).sum();
-            }
-            if random_in_sum < new_out.iter().sum() {
-                new_out = realize_subsum(&new_out, random_in_sum)
-            } else if random_in_sum < out_coins.iter().sum() {
-                out_coins = realize_subsum(&out_coins, random_in_sum)
-            };
-            out_coins.append(&mut new_out);
-        }
-        (transactions, in_coins, out_coins)
-    }
+/// Reads a distribution file written by `build_distribution`, in MessagePack
+/// or JSON.
+pub fn read_distribution<R: Read>(reader: R) -> Result<Distribution, Box<dyn Error>> {
+    read_msgpack_or_json(reader)
+}
 
-    pub fn random_coinjoin_transaction_distributed_shuffled(
-        &self,
-        num_transactions: u64,
-        transaction_size: u64,
-    ) -> (Vec<Transaction>, Set, Set) {
-        let mut transactions: Vec<Transaction> = Vec::new();
-        let mut in_coins: Set = Vec::new();
-        let mut out_sets: Vec<Set> = Vec::new();
-        for _ in 0..num_transactions {
-            let mut new_in = self.random_set(transaction_size);
-            let new_out = self.output_pair(&new_in);
-            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
-            out_sets.push(new_out);
-            in_coins.append(&mut new_in);
-        }
-        let out_coins: Set = out_sets
-            .iter()
-            .flat_map(|out_set| {
-                thread_rng().shuffle(&mut in_coins);
-                let mut random_in_sum: u64 = 0;
-                let out_sum: u64 = out_set.iter().sum();
-                for &coin in in_coins.iter() {
-                    if random_in_sum + coin <= out_sum {
-                        random_in_sum += coin
-                    }
-                }
-                realize_subsum(out_set, random_in_sum)
-            })
-            .collect();
-        (transactions, in_coins, out_coins)
-    }
+/// Reads a shapes file written by `build_distribution`, in MessagePack or
+/// JSON.
+pub fn read_shapes<R: Read>(reader: R) -> Result<ShapeHistogram, Box<dyn Error>> {
+    read_msgpack_or_json(reader)
+}
 
-    fn random_set(&self, n: u64) -> Set {
-        (0..n).map(|_| self.random_coin()).collect()
+fn read_msgpack_or_json<T: DeserializeOwned, R: Read>(reader: R) -> Result<T, Box<dyn Error>> {
+    let mut reader = BufReader::new(reader);
+    let first = reader
+        .fill_buf()?
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .cloned();
+    if first == Some(b'{') {
+        Ok(serde_json::from_reader(reader)?)
+    } else {
+        Ok(rmp_serde::from_read(reader)?)
     }
+}
 
+impl CoinSampler for Distribution {
     fn random_coin(&self) -> u64 {
         loop {
             let Open01(rand) = random::<Open01<f64>>();
@@ -176,14 +93,4 @@ impl Distribution {
             }
         }
     }
-
-    fn output_pair(&self, s: &Vec<u64>) -> Vec<u64> {
-        let sum: u64 = s.iter().sum();
-        loop {
-            let random_output = self.random_coin();
-            if random_output < sum {
-                return vec![random_output, sum - random_output];
-            }
-        }
-    }
 }
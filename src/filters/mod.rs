@@ -6,11 +6,15 @@ use self::num::traits::{One, Zero};
 
 use std::u32;
 
+use budget::SearchBudget;
 use types::{Filter, Partition, Set};
 
 #[cfg(test)]
 mod test;
 
+/// How many subset sums are computed between two checks of the budget.
+const BUDGET_CHECK_INTERVAL: usize = 1024;
+
 /// Solve decision version of subset sum by brute force, returning true if `sum`
 /// can be exactly expressed by summing a subset of `set`. Complexity is
 /// $O(2^n)$ in the size of the set.
@@ -100,6 +104,13 @@ impl<'a> SubsetSumsFilter<'a> {
     /// Initialize the filter. $O(2^n)$ complexity, since the full sumset is
     /// computed in order to construct a bloom filter.
     pub fn new(set: &'a Set) -> SubsetSumsFilter {
+        SubsetSumsFilter::with_budget(set, &SearchBudget::unlimited())
+    }
+
+    /// Like `new`, but stops computing the sumset once the budget is
+    /// exhausted. The filter then misses sums, which is fine since the search
+    /// it is built for stops as well and reports why.
+    pub fn with_budget(set: &'a Set, budget: &SearchBudget) -> SubsetSumsFilter<'a> {
         let mut filter = if set.len() as u32 > u32::MAX {
             BloomFilter::with_rate(0.01, u32::MAX)
         } else {
@@ -108,7 +119,10 @@ impl<'a> SubsetSumsFilter<'a> {
             // exponentially larger in some cases
             BloomFilter::with_rate(0.01, set.len() as u32)
         };
-        for element in SubsetSumIterator::new(set) {
+        for (index, element) in SubsetSumIterator::new(set).enumerate() {
+            if index % BUDGET_CHECK_INTERVAL == 0 && budget.exhausted() {
+                break;
+            }
             filter.insert(&element)
         }
         SubsetSumsFilter {
@@ -1,4 +1,7 @@
+use std::time::{Duration, Instant};
+
 use super::*;
+use budget::{CancellationToken, SearchLimits, SearchStatus};
 use types::Filter;
 
 #[test]
@@ -93,3 +96,20 @@ fn test_subset_sum_set() {
     assert!(!subset_sum_set.contains(&1));
     assert!(!subset_sum_set.contains(&21));
 }
+
+#[test]
+fn test_subset_sum_set_with_budget() {
+    let set: Set = (1..=40).collect();
+    let budget = SearchBudget::new(
+        SearchLimits {
+            max_duration: Some(Duration::from_millis(10)),
+            ..SearchLimits::unlimited()
+        },
+        CancellationToken::new(),
+    );
+    let started = Instant::now();
+    SubsetSumsFilter::with_budget(&set, &budget);
+    // Computing all 2^40 sums would take days.
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert_eq!(budget.status(), SearchStatus::TimedOut);
+}
@@ -0,0 +1,156 @@
+use distribution::Distribution;
+
+#[cfg(test)]
+mod test;
+
+/// Summary statistics of a coin size distribution.
+///
+/// Like `Distribution::random_coin`, the distribution is read as piecewise
+/// linear: the probability mass between two consecutive points, starting at
+/// zero, is spread uniformly over the amounts between them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DistributionSummary {
+    pub points: usize,
+    pub mean: f64,
+    /// Pairs of a probability and the amount below which that share of coins
+    /// lies.
+    pub quantiles: Vec<(f64, u64)>,
+    pub dust_threshold: u64,
+    /// Share of coins below `dust_threshold`.
+    pub dust_mass: f64,
+    /// The points at multiples of `round_unit` with the most probability mass
+    /// and their mass, in descending order.
+    pub round_amounts: Vec<(u64, f64)>,
+    pub round_unit: u64,
+}
+
+impl DistributionSummary {
+    pub fn new(
+        distribution: &Distribution,
+        dust_threshold: u64,
+        round_unit: u64,
+        round_amounts: usize,
+    ) -> DistributionSummary {
+        let points = &distribution.cumulative_normalized;
+        let mut mean = 0f64;
+        let mut round: Vec<(u64, f64)> = Vec::new();
+        let mut previous = (0u64, 0f64);
+        for &(coin, probability) in points {
+            let mass = probability - previous.1;
+            mean += mass * (previous.0 as f64 + coin as f64) / 2f64;
+            if coin > 0 && coin % round_unit == 0 {
+                round.push((coin, mass));
+            }
+            previous = (coin, probability);
+        }
+        round.sort_by(|a, b| b.1.partial_cmp(&a.1).expect("Probabilities are never NaN"));
+        round.truncate(round_amounts);
+        DistributionSummary {
+            points: points.len(),
+            mean,
+            quantiles: [0.01, 0.05, 0.1, 0.25, 0.5, 0.75, 0.9, 0.95, 0.99]
+                .iter()
+                .map(|&p| (p, quantile(distribution, p)))
+                .collect(),
+            dust_threshold,
+            dust_mass: cdf(distribution, dust_threshold as f64),
+            round_amounts: round,
+            round_unit,
+        }
+    }
+}
+
+/// The share of coins with an amount of at most `value`.
+pub fn cdf(distribution: &Distribution, value: f64) -> f64 {
+    let points = &distribution.cumulative_normalized;
+    let i = points.partition_point(|&(coin, _)| (coin as f64) < value);
+    if i == points.len() {
+        return points.last().map_or(0f64, |&(_, p)| p);
+    }
+    let (upper_coin, upper_p) = points[i];
+    let (lower_coin, lower_p) = match i {
+        0 => (0u64, 0f64),
+        _ => points[i - 1],
+    };
+    if upper_coin == lower_coin {
+        return upper_p;
+    }
+    lower_p + (upper_p - lower_p) * (value - lower_coin as f64) / (upper_coin - lower_coin) as f64
+}
+
+/// The smallest amount below which a share of `p` of the coins lies.
+pub fn quantile(distribution: &Distribution, p: f64) -> u64 {
+    let points = &distribution.cumulative_normalized;
+    let i = points.partition_point(|&(_, probability)| probability < p);
+    if i == points.len() {
+        return points.last().map_or(0, |&(coin, _)| coin);
+    }
+    let (upper_coin, upper_p) = points[i];
+    let (lower_coin, lower_p) = match i {
+        0 => (0u64, 0f64),
+        _ => points[i - 1],
+    };
+    if upper_p == lower_p {
+        return upper_coin;
+    }
+    lower_coin + ((upper_coin - lower_coin) as f64 * (p - lower_p) / (upper_p - lower_p)) as u64
+}
+
+/// Samples the cumulative distribution at `points` equally spaced
+/// probabilities, e.g. for plotting. Returns pairs of amount and probability.
+pub fn downsample(distribution: &Distribution, points: usize) -> Vec<(u64, f64)> {
+    (1..=points)
+        .map(|i| {
+            let p = i as f64 / points as f64;
+            (quantile(distribution, p), p)
+        })
+        .collect()
+}
+
+/// The Kolmogorov-Smirnov statistic, the largest difference of the cumulative
+/// distribution functions. Both are piecewise linear, so it is attained at
+/// one of the points.
+pub fn ks_statistic(a: &Distribution, b: &Distribution) -> f64 {
+    breakpoints(a, b)
+        .into_iter()
+        .map(|x| (cdf(a, x) - cdf(b, x)).abs())
+        .fold(0f64, f64::max)
+}
+
+/// The earth mover's distance in satoshis, the area between the cumulative
+/// distribution functions.
+pub fn earth_movers_distance(a: &Distribution, b: &Distribution) -> f64 {
+    let xs = breakpoints(a, b);
+    let mut distance = 0f64;
+    let mut previous = (0f64, 0f64);
+    for x in xs {
+        let difference = cdf(a, x) - cdf(b, x);
+        distance += linear_area(x - previous.0, previous.1, difference);
+        previous = (x, difference);
+    }
+    distance
+}
+
+/// The area between zero and a line from `from` to `to` over a `width`,
+/// counting both signs as positive.
+fn linear_area(width: f64, from: f64, to: f64) -> f64 {
+    if from * to >= 0f64 {
+        return width * (from.abs() + to.abs()) / 2f64;
+    }
+    // The line crosses zero, the two triangles are added separately.
+    let crossing = width * from.abs() / (from.abs() + to.abs());
+    crossing * from.abs() / 2f64 + (width - crossing) * to.abs() / 2f64
+}
+
+/// The points of both distributions in ascending order.
+fn breakpoints(a: &Distribution, b: &Distribution) -> Vec<f64> {
+    let mut xs: Vec<u64> = a
+        .cumulative_normalized
+        .iter()
+        .chain(b.cumulative_normalized.iter())
+        .map(|&(coin, _)| coin)
+        .collect();
+    xs.sort_unstable();
+    xs.dedup();
+    xs.into_iter().map(|x| x as f64).collect()
+}
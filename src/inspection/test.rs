@@ -0,0 +1,53 @@
+use super::*;
+
+/// Uniform between 0 and 100.
+fn uniform() -> Distribution {
+    Distribution::new(vec![(50, 0.5), (100, 1.0)])
+}
+
+#[test]
+fn test_cdf_and_quantile() {
+    let distribution = uniform();
+    assert_eq!(cdf(&distribution, 0.0), 0.0);
+    assert_eq!(cdf(&distribution, 25.0), 0.25);
+    assert_eq!(cdf(&distribution, 100.0), 1.0);
+    assert_eq!(cdf(&distribution, 1000.0), 1.0);
+    assert_eq!(quantile(&distribution, 0.25), 25);
+    assert_eq!(quantile(&distribution, 0.5), 50);
+    assert_eq!(quantile(&distribution, 1.0), 100);
+    assert_eq!(
+        downsample(&distribution, 4),
+        vec![(25, 0.25), (50, 0.5), (75, 0.75), (100, 1.0)]
+    );
+}
+
+#[test]
+fn test_summary() {
+    let distribution = Distribution::new(vec![(100, 0.1), (1000, 0.2), (1100, 0.8), (2000, 1.0)]);
+    let summary = DistributionSummary::new(&distribution, 50, 1000, 2);
+    assert_eq!(summary.points, 4);
+    assert!((summary.mean - (0.1 * 50.0 + 0.1 * 550.0 + 0.6 * 1050.0 + 0.2 * 1550.0)).abs() < 1e-9);
+    assert!((summary.dust_mass - 0.05).abs() < 1e-9);
+    assert_eq!(summary.round_amounts.len(), 2);
+    assert_eq!(summary.round_amounts[0].0, 2000);
+    assert!((summary.round_amounts[0].1 - 0.2).abs() < 1e-9);
+    assert_eq!(summary.round_amounts[1].0, 1000);
+}
+
+#[test]
+fn test_distances() {
+    let a = uniform();
+    assert_eq!(ks_statistic(&a, &a), 0.0);
+    assert_eq!(earth_movers_distance(&a, &a), 0.0);
+    // Uniform between 0 and 200, moving every coin twice as far.
+    let b = Distribution::new(vec![(200, 1.0)]);
+    assert!((ks_statistic(&a, &b) - 0.5).abs() < 1e-9);
+    assert!((earth_movers_distance(&a, &b) - 50.0).abs() < 1e-9);
+    assert_eq!(earth_movers_distance(&a, &b), earth_movers_distance(&b, &a));
+}
+
+#[test]
+fn test_linear_area() {
+    assert_eq!(linear_area(2.0, 1.0, 1.0), 2.0);
+    assert_eq!(linear_area(2.0, -1.0, 1.0), 1.0);
+}
@@ -1,18 +1,74 @@
 extern crate rand;
+extern crate rayon;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate rmp_serde;
 extern crate serde_json;
+extern crate sha2;
+#[cfg(feature = "zstd")]
+extern crate zstd;
 #[macro_use]
 extern crate nom;
 mod types;
-pub use types::{Filter, Partition, Run, Set};
+pub use types::{Filter, Pairing, Partition, Run, Set};
+mod budget;
+pub use budget::{CancellationToken, SearchBudget, SearchLimits, SearchStatus};
+mod progress;
+pub use progress::Progress;
 mod partition;
-pub use partition::SumFilteredPartitionIterator;
+pub use partition::{ParallelSumFilteredPartitions, SumFilteredPartitionIterator};
 mod distribution;
-pub use distribution::Distribution;
+pub use distribution::{read_distribution, read_shapes, Distribution};
+mod counts;
+pub use counts::CoinCounts;
+mod utxo;
+pub use utxo::{PrevoutValues, UtxoSet};
+mod shapes;
+pub use shapes::{ShapeHistogram, ShapeSampler};
+mod sampler;
+pub use sampler::{
+    AmountsSampler, CoinSampler, LogNormalSampler, MixtureComponent, MixtureSampler,
+    RoundBiasedSampler, SamplerSpec, SmoothedDistributionSampler, UniformSampler,
+};
+mod inspection;
+pub use inspection::{
+    cdf, downsample, earth_movers_distance, ks_statistic, quantile, DistributionSummary,
+};
 mod filters;
 pub use filters::{PartitionsSubsetSumsFilter, SubsetSumsFilter};
+mod analyzer;
+pub use analyzer::{match_partitions, Analysis, Analyzer, AnalyzerOptions};
+mod counting;
+pub use counting::{count_mappings, count_mappings_with_budget, MappingCounts};
+mod probability;
+pub use probability::{
+    aggregate_probabilities, AggregatedProbabilities, LinkProbabilities, LINK_CSV_HEADER,
+};
+mod derived;
+pub use derived::filter_derived_partitions;
+mod metrics;
+pub use metrics::{
+    boltzmann_interpretations, entropy, output_anonymity_sets, EntropyMode, PrivacyMetrics,
+};
+mod statistics;
+pub use statistics::{percentile, standard_deviation, Estimate, Summary};
+mod scoring;
+pub use scoring::{GroundTruthScore, ScoreSummary};
+mod results;
+pub use results::{
+    parse_runs, read_results, recover_ndjson, MessagePackWriter, NdjsonWriter, RecoveredNdjson,
+    ResultHeader, RESULT_FORMAT_VERSION,
+};
+mod validation;
+pub use validation::{parse_amount, validate_transaction, ValidatedTransaction, ValidationError};
+mod detection;
+pub use detection::{
+    detect, is_wasabi2_denomination, Candidate, Heuristic, WHIRLPOOL_DENOMINATIONS,
+};
 mod blockchain;
 pub use blockchain::{
-    Block, BlockFileIterator, Outpoint, Transaction, TransactionInput, TransactionOutput,
+    block_hash, expand_block_files, hash_from_hex, hash_to_hex, locate_main_chain, main_chain,
+    scan_block_file, Block, BlockFileIterator, BlockLocation, ChainIterator, Outpoint, Transaction,
+    TransactionInput, TransactionOutput,
 };
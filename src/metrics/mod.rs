@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use derived::filter_derived_partitions;
+use probability::LinkProbabilities;
+use types::{Partition, Set};
+
+#[cfg(test)]
+mod test;
+
+/// Which mappings count as interpretations of a transaction.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntropyMode {
+    /// Only the non-derived mappings, as used for the link probabilities.
+    NonDerived,
+    /// All mappings, counting every one-to-one pairing of sub-transactions
+    /// with equal sums separately. This follows the definition of the number
+    /// of combinations in the Boltzmann tool by LaurentMT, so a 2x2 CoinJoin
+    /// with four equal amounts has 3 interpretations and 1.585 bits of entropy.
+    Boltzmann,
+}
+
+/// Privacy metrics of a transaction derived from its mappings.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PrivacyMetrics {
+    pub mode: EntropyMode,
+    pub interpretations: u64,
+    /// $log_2$ of the number of interpretations, in bits.
+    pub entropy: f64,
+    /// For each output, the number of inputs that are linked to it in at
+    /// least one interpretation.
+    pub output_anonymity_sets: Vec<usize>,
+}
+
+impl PrivacyMetrics {
+    /// `partition_tuples` are all mappings of the transaction, the
+    /// interpretations are selected from them according to `mode`.
+    pub fn new(
+        in_coins: &Set,
+        out_coins: &Set,
+        partition_tuples: &[(Partition, Partition)],
+        mode: EntropyMode,
+    ) -> PrivacyMetrics {
+        let (interpretations, links) = match mode {
+            EntropyMode::NonDerived => {
+                let non_derived = filter_derived_partitions(partition_tuples);
+                let links = LinkProbabilities::new(in_coins, out_coins, &non_derived);
+                (non_derived.len() as u64, links)
+            }
+            EntropyMode::Boltzmann => (
+                boltzmann_interpretations(partition_tuples),
+                LinkProbabilities::new(in_coins, out_coins, partition_tuples),
+            ),
+        };
+        PrivacyMetrics {
+            mode,
+            interpretations,
+            entropy: entropy(interpretations),
+            output_anonymity_sets: output_anonymity_sets(&links),
+        }
+    }
+
+    pub fn min_anonymity_set(&self) -> usize {
+        self.output_anonymity_sets
+            .iter()
+            .cloned()
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn average_anonymity_set(&self) -> f64 {
+        match self.output_anonymity_sets.len() {
+            0 => 0f64,
+            n => self.output_anonymity_sets.iter().sum::<usize>() as f64 / n as f64,
+        }
+    }
+}
+
+/// The entropy in bits of a transaction with the given number of equally
+/// likely interpretations. Zero if there is at most one.
+pub fn entropy(interpretations: u64) -> f64 {
+    match interpretations {
+        0 | 1 => 0f64,
+        n => (n as f64).log2(),
+    }
+}
+
+/// Counts the mappings like Boltzmann counts combinations: each mapping stands
+/// for as many interpretations as there are one-to-one pairings of its input
+/// and output parts with equal sums.
+pub fn boltzmann_interpretations(partition_tuples: &[(Partition, Partition)]) -> u64 {
+    partition_tuples
+        .iter()
+        .map(|(in_partition, _)| {
+            let mut parts_by_sum: HashMap<u64, u64> = HashMap::new();
+            for set in in_partition {
+                *parts_by_sum.entry(set.iter().sum()).or_insert(0) += 1;
+            }
+            parts_by_sum
+                .values()
+                .map(|&k| (1..=k).fold(1u64, |acc, i| acc.saturating_mul(i)))
+                .fold(1u64, |acc, pairings| acc.saturating_mul(pairings))
+        })
+        .fold(0u64, |acc, n| acc.saturating_add(n))
+}
+
+/// The number of inputs with a non-zero link probability to each output.
+pub fn output_anonymity_sets(links: &LinkProbabilities) -> Vec<usize> {
+    (0..links.out_coins.len())
+        .map(|o| links.in_out.iter().filter(|row| row[o] > 0f64).count())
+        .collect()
+}
@@ -0,0 +1,47 @@
+use super::*;
+
+#[test]
+fn test_entropy() {
+    assert_eq!(entropy(0), 0.0);
+    assert_eq!(entropy(1), 0.0);
+    assert_eq!(entropy(8), 3.0);
+}
+
+#[test]
+fn test_boltzmann_interpretations() {
+    // Two participants with equal amounts: both one-to-one pairings and the
+    // merged transaction.
+    let partition_tuples = vec![
+        (vec![vec![5], vec![5]], vec![vec![5], vec![5]]),
+        (vec![vec![5, 5]], vec![vec![5, 5]]),
+    ];
+    assert_eq!(boltzmann_interpretations(&partition_tuples), 3);
+    let metrics = PrivacyMetrics::new(
+        &vec![5, 5],
+        &vec![5, 5],
+        &partition_tuples,
+        EntropyMode::Boltzmann,
+    );
+    assert_eq!(metrics.interpretations, 3);
+    assert!((metrics.entropy - 1.585).abs() < 0.001);
+    assert_eq!(metrics.output_anonymity_sets, vec![2, 2]);
+}
+
+#[test]
+fn test_non_derived_metrics() {
+    let partition_tuples = vec![
+        (vec![vec![1, 2], vec![4]], vec![vec![3], vec![4]]),
+        (vec![vec![1, 2, 4]], vec![vec![3, 4]]),
+    ];
+    let metrics = PrivacyMetrics::new(
+        &vec![1, 2, 4],
+        &vec![3, 4],
+        &partition_tuples,
+        EntropyMode::NonDerived,
+    );
+    assert_eq!(metrics.interpretations, 1);
+    assert_eq!(metrics.entropy, 0.0);
+    assert_eq!(metrics.output_anonymity_sets, vec![2, 1]);
+    assert_eq!(metrics.min_anonymity_set(), 1);
+    assert_eq!(metrics.average_anonymity_set(), 1.5);
+}
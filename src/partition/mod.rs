@@ -1,8 +1,14 @@
+use budget::SearchBudget;
+use rayon::prelude::*;
 use types::{Filter, Partition, Set};
 
 #[cfg(test)]
 mod test;
 
+/// The largest set whose partitions can be enumerated. The powerset of a set
+/// is indexed by a u64 used as a bit vector.
+pub const MAX_SET_SIZE: usize = 64;
+
 enum IterResult<T> {
     End,
     Skip,
@@ -11,9 +17,17 @@ enum IterResult<T> {
 
 /// Given a set and a filter, enumerate all partitions of the set whose parts
 /// match any value in the filter.
+///
+/// If a `SearchBudget` is given, the enumeration ends early once the budget is
+/// exhausted; `SearchBudget::status` then tells whether the partitions
+/// returned so far are all there are.
 pub struct SumFilteredPartitionIterator<'a> {
     set: Set,
     filter: &'a dyn Filter<u64>,
+    budget: Option<&'a SearchBudget>,
+    // Only the outermost iterator counts towards the partition limit, nested
+    // iterators return partitions of subsets.
+    count_partitions: bool,
     tuple_iterator: TupleIterator,
     left_set: Option<Set>,
     left_set_sum: u64,
@@ -22,12 +36,31 @@ pub struct SumFilteredPartitionIterator<'a> {
 
 impl<'a> SumFilteredPartitionIterator<'a> {
     pub fn new(set: Set, filter: &'a dyn Filter<u64>) -> SumFilteredPartitionIterator {
+        SumFilteredPartitionIterator::build(set, filter, None, true)
+    }
+
+    pub fn with_budget(
+        set: Set,
+        filter: &'a dyn Filter<u64>,
+        budget: &'a SearchBudget,
+    ) -> SumFilteredPartitionIterator<'a> {
+        SumFilteredPartitionIterator::build(set, filter, Some(budget), true)
+    }
+
+    fn build(
+        set: Set,
+        filter: &'a dyn Filter<u64>,
+        budget: Option<&'a SearchBudget>,
+        count_partitions: bool,
+    ) -> SumFilteredPartitionIterator<'a> {
         let mut tuple_iterator = TupleIterator::new(set.clone());
         match tuple_iterator.next() {
             // This case is triggered when `set` is a singleton
             None => SumFilteredPartitionIterator {
                 set: set.clone(),
                 filter,
+                budget,
+                count_partitions,
                 tuple_iterator,
                 left_set_sum: set.iter().sum(),
                 left_set: Some(set),
@@ -40,16 +73,41 @@ impl<'a> SumFilteredPartitionIterator<'a> {
             Some((left, right)) => SumFilteredPartitionIterator {
                 set,
                 filter,
+                budget,
+                count_partitions,
                 tuple_iterator,
                 left_set_sum: left.iter().sum(),
                 left_set: Some(left),
-                right_partitions_iterator: Some(Box::new(SumFilteredPartitionIterator::new(
-                    right, filter,
+                right_partitions_iterator: Some(Box::new(SumFilteredPartitionIterator::build(
+                    right, filter, budget, false,
                 ))),
             },
         }
     }
 
+    fn nested(&self, set: Set) -> Box<SumFilteredPartitionIterator<'a>> {
+        Box::new(SumFilteredPartitionIterator::build(
+            set,
+            self.filter,
+            self.budget,
+            false,
+        ))
+    }
+
+    fn contains(&self, sum: &u64) -> bool {
+        if let Some(budget) = self.budget {
+            budget.record_filter_call();
+        }
+        self.filter.contains(sum)
+    }
+
+    fn exhausted(&self) -> bool {
+        match self.budget {
+            Some(budget) => budget.exhausted(),
+            None => false,
+        }
+    }
+
     /// Complexity is exponential, a bit hard to quantify exactly how much
     /// especially in the average case as opposed to worst case.
     /// This is due to potentially high complexity of filter queries, higher than
@@ -57,6 +115,9 @@ impl<'a> SumFilteredPartitionIterator<'a> {
     /// to filter.contains() on true positives.
     /// Theoretically $O(2^n)$.
     fn next(&mut self) -> IterResult<Partition> {
+        if self.exhausted() {
+            return IterResult::End;
+        }
         let left_set = match self.left_set.clone() {
             Some(set) => set,
             None => return IterResult::End,
@@ -66,7 +127,7 @@ impl<'a> SumFilteredPartitionIterator<'a> {
         // cases is significant, this will multiply it by the number of
         // partitions of the right set since the filter will be re-evaluated
         // wrt the left set repeatedly even as it remains fixed.
-        let sum_contained = { self.filter.contains(&self.left_set_sum) };
+        let sum_contained = { self.contains(&self.left_set_sum) };
         if !sum_contained {
             // If the left set doesn't match the fitler, a different
             // subset/complement split is needed.
@@ -77,7 +138,7 @@ impl<'a> SumFilteredPartitionIterator<'a> {
                     self.left_set = None;
                     self.right_partitions_iterator = None;
                     let set_sum = self.set.iter().sum();
-                    let sum_contained = { self.filter.contains(&set_sum) };
+                    let sum_contained = { self.contains(&set_sum) };
                     return if sum_contained {
                         IterResult::Element(vec![self.set.clone()])
                     } else {
@@ -93,9 +154,7 @@ impl<'a> SumFilteredPartitionIterator<'a> {
                 Some((left, right)) => {
                     self.left_set = Some(left.clone());
                     self.left_set_sum = left.iter().sum();
-                    self.right_partitions_iterator = Some(Box::new(
-                        SumFilteredPartitionIterator::new(right.clone(), self.filter),
-                    ));
+                    self.right_partitions_iterator = Some(self.nested(right));
                     return IterResult::Skip;
                 }
             }
@@ -117,6 +176,9 @@ impl<'a> SumFilteredPartitionIterator<'a> {
                 partition.push(left_set);
                 IterResult::Element(partition)
             }
+            // A nested iterator may also have ended because the budget ran
+            // out, in which case the remaining subsets must not be visited.
+            None if self.exhausted() => IterResult::End,
             // Otherwise, advance the tuple iterator to obtain the next left set.
             None => match self.tuple_iterator.next() {
                 // If it is depleted, the trivial partition is returned.
@@ -135,9 +197,7 @@ impl<'a> SumFilteredPartitionIterator<'a> {
                 Some((left, right)) => {
                     self.left_set = Some(left.clone());
                     self.left_set_sum = left.iter().sum();
-                    self.right_partitions_iterator = Some(Box::new(
-                        SumFilteredPartitionIterator::new(right.clone(), self.filter),
-                    ));
+                    self.right_partitions_iterator = Some(self.nested(right));
                     IterResult::Skip
                 }
             },
@@ -151,7 +211,12 @@ impl<'a> Iterator for SumFilteredPartitionIterator<'a> {
     fn next(&mut self) -> Option<Partition> {
         loop {
             match self.next() {
-                IterResult::Element(p) => return Some(p),
+                IterResult::Element(p) => {
+                    if let (true, Some(budget)) = (self.count_partitions, self.budget) {
+                        budget.record_partition();
+                    }
+                    return Some(p);
+                }
                 IterResult::End => return None,
                 IterResult::Skip => {}
             }
@@ -159,6 +224,139 @@ impl<'a> Iterator for SumFilteredPartitionIterator<'a> {
     }
 }
 
+/// The number of ranges of top level subsets handed out to the thread pool by
+/// `ParallelSumFilteredPartitions`. Much larger than the number of threads, so
+/// that uneven ranges still keep all threads busy.
+const PARALLEL_CHUNKS: u64 = 1024;
+
+/// Enumerates the same partitions as `SumFilteredPartitionIterator`, in the same
+/// order, using the rayon thread pool.
+///
+/// The work is split at the top level: the subset/complement pairs of the
+/// `TupleIterator` over the whole set are divided into ranges, and each range
+/// is processed sequentially by nested `SumFilteredPartitionIterator`s.
+///
+/// This is not a rayon `ParallelIterator`: `collect_parallel` blocks until all
+/// ranges are done and returns the partitions in one `Vec`, so they cannot be
+/// consumed while the search is still running.
+pub struct ParallelSumFilteredPartitions<'a> {
+    set: Set,
+    filter: &'a (dyn Filter<u64> + Sync),
+    budget: Option<&'a SearchBudget>,
+}
+
+impl<'a> ParallelSumFilteredPartitions<'a> {
+    pub fn new(
+        set: Set,
+        filter: &'a (dyn Filter<u64> + Sync),
+    ) -> ParallelSumFilteredPartitions<'a> {
+        ParallelSumFilteredPartitions {
+            set,
+            filter,
+            budget: None,
+        }
+    }
+
+    pub fn with_budget(
+        set: Set,
+        filter: &'a (dyn Filter<u64> + Sync),
+        budget: &'a SearchBudget,
+    ) -> ParallelSumFilteredPartitions<'a> {
+        ParallelSumFilteredPartitions {
+            set,
+            filter,
+            budget: Some(budget),
+        }
+    }
+
+    /// Enumerates all partitions on the thread pool and returns them in the
+    /// order of `SumFilteredPartitionIterator`. If the budget runs out, the
+    /// result is a subset of the sequential one.
+    pub fn collect_parallel(self) -> Vec<Partition> {
+        let max_pattern = TupleIterator::new(self.set.clone()).max_pattern;
+        // Without non-trivial splits there is nothing to parallelize.
+        if max_pattern == 0 {
+            return match self.budget {
+                Some(budget) => {
+                    SumFilteredPartitionIterator::with_budget(self.set, self.filter, budget)
+                        .collect()
+                }
+                None => SumFilteredPartitionIterator::new(self.set, self.filter).collect(),
+            };
+        }
+        let chunks = max_pattern.min(PARALLEL_CHUNKS);
+        let mut results: Vec<Vec<Partition>> = Vec::new();
+        (0..chunks as usize)
+            .into_par_iter()
+            .weight_max()
+            .map(|chunk| {
+                let chunk = chunk as u128;
+                let first = 1 + (max_pattern as u128 * chunk / chunks as u128) as u64;
+                let last = (max_pattern as u128 * (chunk + 1) / chunks as u128) as u64;
+                self.partitions_in_range(first, last)
+            })
+            .collect_into(&mut results);
+        let mut partitions: Vec<Partition> = results.into_iter().flatten().collect();
+
+        // The sequential iterator returns the trivial partition after the last
+        // subset, which is always the first element alone. It skips the check
+        // of the full sum if that subset matched, see the FIXME there.
+        if !self.exhausted() {
+            let first = self.set[0];
+            let set_sum = self.set.iter().sum();
+            if self.contains(&first) || self.contains(&set_sum) {
+                self.record_partition();
+                partitions.push(vec![self.set.clone()]);
+            }
+        }
+        partitions
+    }
+
+    fn partitions_in_range(&self, first_pattern: u64, last_pattern: u64) -> Vec<Partition> {
+        let mut partitions = Vec::new();
+        let tuples = TupleIterator::with_patterns(self.set.clone(), first_pattern, last_pattern);
+        'tuples: for (left, right) in tuples {
+            if self.exhausted() {
+                break;
+            }
+            if !self.contains(&left.iter().sum()) {
+                continue;
+            }
+            let right_partitions =
+                SumFilteredPartitionIterator::build(right, self.filter, self.budget, false);
+            for mut partition in right_partitions {
+                if self.exhausted() {
+                    break 'tuples;
+                }
+                self.record_partition();
+                partition.push(left.clone());
+                partitions.push(partition);
+            }
+        }
+        partitions
+    }
+
+    fn contains(&self, sum: &u64) -> bool {
+        if let Some(budget) = self.budget {
+            budget.record_filter_call();
+        }
+        self.filter.contains(sum)
+    }
+
+    fn exhausted(&self) -> bool {
+        match self.budget {
+            Some(budget) => budget.exhausted(),
+            None => false,
+        }
+    }
+
+    fn record_partition(&self) {
+        if let Some(budget) = self.budget {
+            budget.record_partition();
+        }
+    }
+}
+
 /// Enumerates all 2-partitions (all pairs of a non-empty proper subset and its
 /// complement, distinct up to equality of unordered pairs) of a Set.
 ///
@@ -173,9 +371,7 @@ pub struct TupleIterator {
 
 impl TupleIterator {
     fn new(set: Set) -> TupleIterator {
-        // The powerset is indexed by a u64 used as a bit vector, so sizes
-        // larger than 64 are not supported.
-        assert!(set.len() <= 64);
+        assert!(set.len() <= MAX_SET_SIZE);
 
         let first = match set.first() {
             Some(v) => v.to_owned(),
@@ -193,6 +389,15 @@ impl TupleIterator {
             max_pattern,
         }
     }
+
+    /// Only enumerates the 2-partitions with index `first_pattern` up to and
+    /// including `last_pattern`, counting from 1 in the order of `new`.
+    fn with_patterns(set: Set, first_pattern: u64, last_pattern: u64) -> TupleIterator {
+        let mut iterator = TupleIterator::new(set);
+        iterator.current_pattern = first_pattern;
+        iterator.max_pattern = iterator.max_pattern.min(last_pattern);
+        iterator
+    }
 }
 
 impl Iterator for TupleIterator {
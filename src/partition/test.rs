@@ -1,6 +1,8 @@
 use serde_json;
 
 use super::*;
+use analyzer::Analyzer;
+use budget::{CancellationToken, SearchBudget, SearchLimits, SearchStatus};
 use filters::{PartitionsSubsetSumsFilter, SubsetSumsFilter};
 use types::{Partition, Run, Set};
 
@@ -15,6 +17,49 @@ fn test_sum_filtered_partition_iterator() {
     );
 }
 
+#[test]
+fn test_sum_filtered_partition_iterator_with_budget() {
+    let set = vec![1, 2, 3, 4, 5, 6];
+    let subsetsum = &SubsetSumsFilter::new(&set);
+    let all: Vec<Partition> = SumFilteredPartitionIterator::new(set.clone(), subsetsum).collect();
+
+    let budget = SearchBudget::unlimited();
+    let unlimited: Vec<Partition> =
+        SumFilteredPartitionIterator::with_budget(set.clone(), subsetsum, &budget).collect();
+    assert_eq!(unlimited, all);
+    assert_eq!(budget.status(), SearchStatus::Complete);
+    assert_eq!(budget.partitions(), all.len() as u64);
+    assert!(budget.filter_calls() > 0);
+
+    let limits = SearchLimits {
+        max_partitions: Some(5),
+        ..SearchLimits::default()
+    };
+    let budget = SearchBudget::new(limits, CancellationToken::new());
+    let truncated: Vec<Partition> =
+        SumFilteredPartitionIterator::with_budget(set.clone(), subsetsum, &budget).collect();
+    assert_eq!(truncated, all[..5].to_vec());
+    assert_eq!(budget.status(), SearchStatus::PartitionLimitReached);
+
+    let limits = SearchLimits {
+        max_filter_calls: Some(3),
+        ..SearchLimits::default()
+    };
+    let budget = SearchBudget::new(limits, CancellationToken::new());
+    let truncated: Vec<Partition> =
+        SumFilteredPartitionIterator::with_budget(set.clone(), subsetsum, &budget).collect();
+    assert!(truncated.len() < all.len());
+    assert_eq!(budget.status(), SearchStatus::FilterCallLimitReached);
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let budget = SearchBudget::new(SearchLimits::unlimited(), token);
+    let cancelled: Vec<Partition> =
+        SumFilteredPartitionIterator::with_budget(set.clone(), subsetsum, &budget).collect();
+    assert!(cancelled.is_empty());
+    assert_eq!(budget.status(), SearchStatus::Cancelled);
+}
+
 #[test]
 fn regression_test_sum_filtered_partition_iterator() {
     let none_shuffled = include_str!("result-none-t-3-s-2-r-1.json");
@@ -24,27 +69,9 @@ fn regression_test_sum_filtered_partition_iterator() {
     let mut counter: u64 = 0;
     for file in test_files {
         let run: Run = serde_json::from_str(file).expect("Invalid json in input");
-        let in_coins = run.in_coins;
-        let out_coins = run.out_coins;
-        let in_partitions: Vec<Partition> = {
-            SumFilteredPartitionIterator::new(in_coins.clone(), &SubsetSumsFilter::new(&out_coins))
-                .collect()
-        };
-        let out_partitions: Vec<Partition> = {
-            SumFilteredPartitionIterator::new(
-                out_coins.clone(),
-                &PartitionsSubsetSumsFilter::new(&in_partitions),
-            )
-            .collect()
-        };
-        let mut partition_tuples: Vec<(Partition, Partition)> = Vec::new();
-        for in_partition in in_partitions {
-            for out_partition in out_partitions.clone() {
-                if partitions_match(&in_partition, &out_partition) {
-                    partition_tuples.push((in_partition.clone(), out_partition.clone()));
-                }
-            }
-        }
+        let partition_tuples = Analyzer::default()
+            .analyze(&run.in_coins, &run.out_coins)
+            .partition_tuples;
         for (in_partition, out_partition) in run.partition_tuples {
             counter += 1;
             assert!(
@@ -59,18 +86,6 @@ fn regression_test_sum_filtered_partition_iterator() {
     assert_eq!(counter, 30);
 }
 
-fn partitions_match(a: &Partition, b: &Partition) -> bool {
-    'outer: for set_a in a {
-        for set_b in b {
-            if set_a.iter().sum::<u64>() == set_b.iter().sum::<u64>() {
-                continue 'outer;
-            }
-        }
-        return false;
-    }
-    true
-}
-
 fn partition_eq(part_a: &Partition, part_b: &Partition) -> bool {
     part_a
         .iter()
@@ -83,6 +98,64 @@ fn set_eq(set_a: &Set, set_b: &Set) -> bool {
         .all(|element_a| set_b.iter().any(|element_b| element_a == element_b))
 }
 
+#[test]
+fn test_parallel_sum_filtered_partitions() {
+    let set = vec![1, 2, 3, 4, 5, 6, 7];
+    let subsetsum = &SubsetSumsFilter::new(&set);
+    assert_eq!(
+        ParallelSumFilteredPartitions::new(set.clone(), subsetsum).collect_parallel(),
+        SumFilteredPartitionIterator::new(set.clone(), subsetsum).collect::<Vec<Partition>>()
+    );
+    let filter_set = vec![3, 4, 19];
+    let subsetsum = &SubsetSumsFilter::new(&filter_set);
+    assert_eq!(
+        ParallelSumFilteredPartitions::new(vec![1, 3, 18], subsetsum).collect_parallel(),
+        SumFilteredPartitionIterator::new(vec![1, 3, 18], subsetsum).collect::<Vec<Partition>>()
+    );
+    assert_eq!(
+        ParallelSumFilteredPartitions::new(vec![22], subsetsum).collect_parallel(),
+        vec![vec![vec![22]]]
+    );
+
+    for file in vec![
+        include_str!("result-none-t-3-s-2-r-1.json"),
+        include_str!("result-output-t-3-s-2-r-1.json"),
+        include_str!("result-input-t-3-s-2-r-1.json"),
+    ] {
+        let run: Run = serde_json::from_str(file).expect("Invalid json in input");
+        let subsetsum = &SubsetSumsFilter::new(&run.out_coins);
+        let in_partitions: Vec<Partition> =
+            SumFilteredPartitionIterator::new(run.in_coins.clone(), subsetsum).collect();
+        assert_eq!(
+            ParallelSumFilteredPartitions::new(run.in_coins.clone(), subsetsum).collect_parallel(),
+            in_partitions
+        );
+        let partitions_filter = &PartitionsSubsetSumsFilter::new(&in_partitions);
+        assert_eq!(
+            ParallelSumFilteredPartitions::new(run.out_coins.clone(), partitions_filter)
+                .collect_parallel(),
+            SumFilteredPartitionIterator::new(run.out_coins.clone(), partitions_filter)
+                .collect::<Vec<Partition>>()
+        );
+    }
+
+    let limits = SearchLimits {
+        max_partitions: Some(5),
+        ..SearchLimits::default()
+    };
+    let budget = SearchBudget::new(limits, CancellationToken::new());
+    let set = vec![1, 2, 3, 4, 5, 6, 7];
+    let subsetsum = &SubsetSumsFilter::new(&set);
+    let truncated = ParallelSumFilteredPartitions::with_budget(set.clone(), subsetsum, &budget)
+        .collect_parallel();
+    let all: Vec<Partition> = SumFilteredPartitionIterator::new(set.clone(), subsetsum).collect();
+    assert!(truncated.len() >= 5);
+    assert!(truncated.len() < all.len());
+    assert!(truncated.iter().all(|partition| all.contains(partition)));
+    assert!(!budget.status().is_complete());
+    assert_eq!(budget.status(), SearchStatus::PartitionLimitReached);
+}
+
 #[test]
 fn test_tuple_iterator() {
     assert_eq!(
@@ -100,5 +173,9 @@ fn test_tuple_iterator() {
             (vec![1, 2], vec![3]),
             (vec![1], vec![2, 3])
         ]
+    );
+    assert_eq!(
+        TupleIterator::with_patterns(vec![1, 2, 3], 2, 3).collect::<Vec<(Set, Set)>>(),
+        vec![(vec![1, 2], vec![3]), (vec![1], vec![2, 3])]
     )
 }
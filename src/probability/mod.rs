@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use types::{Partition, Set};
+
+#[cfg(test)]
+mod test;
+
+/// The probabilities that coins of a transaction belong together, estimated as
+/// the fraction of mappings in which they do.
+///
+/// An input and an output are linked in a mapping if the parts containing them
+/// have the same sum, two inputs or two outputs are linked if they are in the
+/// same part. The matrices are indexed by the position of the coins in
+/// `in_coins` and `out_coins`. Without mappings nothing is known about the
+/// links and all probabilities are NaN.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LinkProbabilities {
+    pub in_coins: Set,
+    pub out_coins: Set,
+    pub mappings: usize,
+    pub in_out: Vec<Vec<f64>>,
+    pub in_in: Vec<Vec<f64>>,
+    pub out_out: Vec<Vec<f64>>,
+}
+
+/// Summary of a collection of probabilities.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct AggregatedProbabilities {
+    pub zeros: u64,
+    pub ones: u64,
+    /// Average of the probabilities that are neither zero nor one.
+    pub average_other: f64,
+    pub average: f64,
+}
+
+/// The header of the rows written by `LinkProbabilities::write_csv`.
+pub const LINK_CSV_HEADER: &str =
+    "transaction,link,first_index,first_amount,second_index,second_amount,probability";
+
+impl LinkProbabilities {
+    /// Computes all link probabilities in a single pass over the mappings,
+    /// all NaN if there are none, e.g. because the search was cut short.
+    /// Complexity is $O(m(n_i + n_o)^2)$ for $m$ mappings of $n_i$ inputs
+    /// and $n_o$ outputs.
+    pub fn new(
+        in_coins: &Set,
+        out_coins: &Set,
+        partition_tuples: &[(Partition, Partition)],
+    ) -> LinkProbabilities {
+        let mut in_out = vec![vec![0u64; out_coins.len()]; in_coins.len()];
+        let mut in_in = vec![vec![0u64; in_coins.len()]; in_coins.len()];
+        let mut out_out = vec![vec![0u64; out_coins.len()]; out_coins.len()];
+        for (in_partition, out_partition) in partition_tuples {
+            let in_parts = part_indices(in_coins, in_partition);
+            let out_parts = part_indices(out_coins, out_partition);
+            let in_sums: Vec<u64> = in_partition.iter().map(|set| set.iter().sum()).collect();
+            let out_sums: Vec<u64> = out_partition.iter().map(|set| set.iter().sum()).collect();
+            for (i, &in_part) in in_parts.iter().enumerate() {
+                for (o, &out_part) in out_parts.iter().enumerate() {
+                    if in_sums[in_part] == out_sums[out_part] {
+                        in_out[i][o] += 1;
+                    }
+                }
+            }
+            count_same_part(&in_parts, &mut in_in);
+            count_same_part(&out_parts, &mut out_out);
+        }
+        // Without mappings this divides zero by zero, so all probabilities are
+        // NaN instead of claiming that no coins are linked.
+        let mappings = partition_tuples.len() as f64;
+        let normalize = |counts: Vec<Vec<u64>>| -> Vec<Vec<f64>> {
+            counts
+                .into_iter()
+                .map(|row| row.into_iter().map(|c| c as f64 / mappings).collect())
+                .collect()
+        };
+        LinkProbabilities {
+            in_coins: in_coins.clone(),
+            out_coins: out_coins.clone(),
+            mappings: partition_tuples.len(),
+            in_out: normalize(in_out),
+            in_in: normalize(in_in),
+            out_out: normalize(out_out),
+        }
+    }
+
+    /// Aggregates the probabilities of all input-output pairs, `None` without
+    /// mappings.
+    pub fn aggregated_in_out(&self) -> Option<AggregatedProbabilities> {
+        let probabilities: Vec<f64> = self.in_out.iter().flat_map(|row| row.clone()).collect();
+        self.aggregate(&probabilities)
+    }
+
+    /// Aggregates the probabilities of all unordered pairs of distinct inputs,
+    /// `None` without mappings.
+    pub fn aggregated_in_in(&self) -> Option<AggregatedProbabilities> {
+        self.aggregate(&upper_triangle(&self.in_in))
+    }
+
+    /// Aggregates the probabilities of all unordered pairs of distinct outputs,
+    /// `None` without mappings.
+    pub fn aggregated_out_out(&self) -> Option<AggregatedProbabilities> {
+        self.aggregate(&upper_triangle(&self.out_out))
+    }
+
+    fn aggregate(&self, probabilities: &[f64]) -> Option<AggregatedProbabilities> {
+        match self.mappings {
+            0 => None,
+            _ => Some(aggregate_probabilities(probabilities)),
+        }
+    }
+
+    /// Writes one CSV row per pair of coins, see `LINK_CSV_HEADER`.
+    /// `transaction` identifies the transaction when rows of several are
+    /// written to the same file. Input-input and output-output rows are only
+    /// written for pairs of distinct coins, in index order. Probabilities are
+    /// written as `NA` without mappings.
+    pub fn write_csv<W: Write>(&self, writer: &mut W, transaction: usize) -> io::Result<()> {
+        for (i, row) in self.in_out.iter().enumerate() {
+            for (o, probability) in row.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "{},input_output,{},{},{},{},{}",
+                    transaction,
+                    i,
+                    self.in_coins[i],
+                    o,
+                    self.out_coins[o],
+                    format_probability(*probability)
+                )?;
+            }
+        }
+        write_pairs_csv(
+            writer,
+            transaction,
+            "input_input",
+            &self.in_coins,
+            &self.in_in,
+        )?;
+        write_pairs_csv(
+            writer,
+            transaction,
+            "output_output",
+            &self.out_coins,
+            &self.out_out,
+        )
+    }
+}
+
+fn write_pairs_csv<W: Write>(
+    writer: &mut W,
+    transaction: usize,
+    link: &str,
+    coins: &Set,
+    matrix: &[Vec<f64>],
+) -> io::Result<()> {
+    for (i, row) in matrix.iter().enumerate() {
+        for (j, probability) in row.iter().enumerate().skip(i + 1) {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                transaction,
+                link,
+                i,
+                coins[i],
+                j,
+                coins[j],
+                format_probability(*probability)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn format_probability(probability: f64) -> String {
+    if probability.is_nan() {
+        "NA".to_string()
+    } else {
+        probability.to_string()
+    }
+}
+
+pub fn aggregate_probabilities(probabilities: &[f64]) -> AggregatedProbabilities {
+    let zeros = probabilities.iter().filter(|&&p| p == 0f64).count() as u64;
+    let ones = probabilities.iter().filter(|&&p| p == 1f64).count() as u64;
+    let other: Vec<f64> = probabilities
+        .iter()
+        .filter(|&&p| p > 0f64 && p < 1f64)
+        .copied()
+        .collect();
+    AggregatedProbabilities {
+        zeros,
+        ones,
+        average_other: average(&other),
+        average: average(probabilities),
+    }
+}
+
+fn average(v: &[f64]) -> f64 {
+    match v.len() {
+        0 => 0f64,
+        _ => v.iter().sum::<f64>() / v.len() as f64,
+    }
+}
+
+fn upper_triangle(matrix: &[Vec<f64>]) -> Vec<f64> {
+    matrix
+        .iter()
+        .enumerate()
+        .flat_map(|(i, row)| row.iter().skip(i + 1).copied())
+        .collect()
+}
+
+fn count_same_part(parts: &[usize], counts: &mut [Vec<u64>]) {
+    for (i, &part_i) in parts.iter().enumerate() {
+        for (j, &part_j) in parts.iter().enumerate() {
+            if part_i == part_j {
+                counts[i][j] += 1;
+            }
+        }
+    }
+}
+
+/// Finds the index of the part of `partition` containing each coin. Partitions
+/// only hold coin values, so coins with equal values are assigned to the parts
+/// containing that value in order of appearance.
+pub fn part_indices(coins: &Set, partition: &Partition) -> Vec<usize> {
+    let mut parts_by_value: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (index, set) in partition.iter().enumerate().rev() {
+        for coin in set {
+            parts_by_value.entry(*coin).or_default().push(index);
+        }
+    }
+    coins
+        .iter()
+        .map(
+            |coin| match parts_by_value.get_mut(coin).and_then(|parts| parts.pop()) {
+                Some(index) => index,
+                None => panic!("Did not find coin {} in partition", coin),
+            },
+        )
+        .collect()
+}
@@ -0,0 +1,100 @@
+use super::*;
+
+#[test]
+fn test_link_probabilities() {
+    let in_coins = vec![1, 2, 4];
+    let out_coins = vec![3, 4];
+    let partition_tuples = vec![
+        (vec![vec![1, 2], vec![4]], vec![vec![3], vec![4]]),
+        (vec![vec![1, 2, 4]], vec![vec![3, 4]]),
+    ];
+    let probabilities = LinkProbabilities::new(&in_coins, &out_coins, &partition_tuples);
+    assert_eq!(
+        probabilities.in_out,
+        vec![vec![1.0, 0.5], vec![1.0, 0.5], vec![0.5, 1.0]]
+    );
+    assert_eq!(
+        probabilities.in_in,
+        vec![
+            vec![1.0, 1.0, 0.5],
+            vec![1.0, 1.0, 0.5],
+            vec![0.5, 0.5, 1.0]
+        ]
+    );
+    assert_eq!(probabilities.out_out, vec![vec![1.0, 0.5], vec![0.5, 1.0]]);
+    assert_eq!(
+        probabilities.aggregated_in_out(),
+        Some(AggregatedProbabilities {
+            zeros: 0,
+            ones: 3,
+            average_other: 0.5,
+            average: 0.75,
+        })
+    );
+    assert_eq!(
+        probabilities.aggregated_in_in(),
+        Some(AggregatedProbabilities {
+            zeros: 0,
+            ones: 1,
+            average_other: 0.5,
+            average: 2.0 / 3.0,
+        })
+    );
+}
+
+#[test]
+fn test_write_csv() {
+    let partition_tuples = vec![
+        (vec![vec![1, 2], vec![4]], vec![vec![3], vec![4]]),
+        (vec![vec![1, 2, 4]], vec![vec![3, 4]]),
+    ];
+    let probabilities = LinkProbabilities::new(&vec![1, 2, 4], &vec![3, 4], &partition_tuples);
+    let mut csv: Vec<u8> = Vec::new();
+    probabilities.write_csv(&mut csv, 7).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows.len(), 3 * 2 + 3 + 1);
+    assert_eq!(rows[0], "7,input_output,0,1,0,3,1");
+    assert_eq!(rows[1], "7,input_output,0,1,1,4,0.5");
+    assert_eq!(rows[6], "7,input_input,0,1,1,2,1");
+    assert_eq!(rows[9], "7,output_output,0,3,1,4,0.5");
+}
+
+#[test]
+fn test_part_indices() {
+    let partition = vec![vec![5, 1], vec![5], vec![2]];
+    assert_eq!(
+        part_indices(&vec![1, 2, 5, 5], &partition),
+        vec![0, 2, 0, 1]
+    );
+}
+
+#[test]
+fn test_aggregate_probabilities() {
+    assert_eq!(
+        aggregate_probabilities(&[0.0, 1.0, 0.25, 0.75]),
+        AggregatedProbabilities {
+            zeros: 1,
+            ones: 1,
+            average_other: 0.5,
+            average: 0.5,
+        }
+    );
+    assert_eq!(aggregate_probabilities(&[]).average, 0.0);
+}
+
+#[test]
+fn test_link_probabilities_without_mappings() {
+    let links = LinkProbabilities::new(&vec![5], &vec![3], &[]);
+    assert!(links.in_out[0][0].is_nan());
+    assert!(links.in_in[0][0].is_nan());
+    assert!(links.out_out[0][0].is_nan());
+    assert_eq!(links.aggregated_in_out(), None);
+    assert_eq!(links.aggregated_in_in(), None);
+    let mut csv: Vec<u8> = Vec::new();
+    links.write_csv(&mut csv, 0).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "0,input_output,0,5,0,3,NA\n"
+    );
+}
@@ -0,0 +1,52 @@
+use std::io::Write;
+
+/// Shows how many of a number of steps are done on a single line of `writer`,
+/// updated whenever another percent is done. Tools that print results to the
+/// standard output show it on the standard error.
+pub struct Progress<W: Write> {
+    writer: W,
+    label: &'static str,
+    total: usize,
+    done: usize,
+    percent: Option<usize>,
+}
+
+impl<W: Write> Progress<W> {
+    pub fn new(writer: W, label: &'static str, total: usize) -> Progress<W> {
+        let mut progress = Progress {
+            writer,
+            label,
+            total,
+            done: 0,
+            percent: None,
+        };
+        progress.show();
+        progress
+    }
+
+    pub fn advance(&mut self) {
+        self.done += 1;
+        self.show();
+    }
+
+    fn show(&mut self) {
+        let percent = match self.total {
+            0 => 100,
+            total => self.done * 100 / total,
+        };
+        if self.percent == Some(percent) {
+            return;
+        }
+        self.percent = Some(percent);
+        // Progress is only informative, so failing to show it is ignored.
+        let _ = write!(
+            self.writer,
+            "\r{}: {}/{} ({}%)",
+            self.label, self.done, self.total, percent
+        );
+        if self.done == self.total {
+            let _ = writeln!(self.writer);
+        }
+        let _ = self.writer.flush();
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use rmp_serde;
+use serde_json;
+
+use types::Run;
+
+#[cfg(test)]
+mod test;
+
+/// Parses the runs of a result file, either a JSON array as written by
+/// `cja auto` or newline delimited JSON with one run per line.
+pub fn parse_runs(input: &str) -> Result<Vec<Run>, serde_json::Error> {
+    if input.trim_start().starts_with('[') {
+        return serde_json::from_str(input);
+    }
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// Writes runs as newline delimited JSON, flushing after every run so that
+/// finished runs survive a crash.
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> NdjsonWriter<W> {
+        NdjsonWriter { writer }
+    }
+
+    pub fn write_run(&mut self, run: &Run) -> io::Result<()> {
+        let line = serde_json::to_string(run)?;
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()
+    }
+}
+
+/// The runs of a newline delimited JSON result file read by `recover_ndjson`.
+pub struct RecoveredNdjson {
+    pub runs: Vec<Run>,
+    path: PathBuf,
+    /// The length of the complete lines, `None` if there is no file.
+    complete_length: Option<u64>,
+}
+
+impl RecoveredNdjson {
+    /// Removes a last line that was only partially written, so that new runs
+    /// can be appended to the file.
+    pub fn truncate(&self) -> io::Result<()> {
+        match self.complete_length {
+            Some(length) => OpenOptions::new()
+                .write(true)
+                .open(&self.path)?
+                .set_len(length),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads the runs of a newline delimited JSON result file without changing
+/// it. A last line that was only partially written, for example because the
+/// process was killed, is ignored and removed by `RecoveredNdjson::truncate`.
+/// Any other line that is no run is an error. A missing file holds no runs.
+pub fn recover_ndjson(path: &Path) -> io::Result<RecoveredNdjson> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(RecoveredNdjson {
+                runs: Vec::new(),
+                path: path.to_path_buf(),
+                complete_length: None,
+            })
+        }
+        Err(e) => return Err(e),
+    };
+    let mut reader = BufReader::new(file);
+    let mut runs = Vec::new();
+    let mut complete_length = 0u64;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+        if !line.trim().is_empty() {
+            let run = serde_json::from_str::<Run>(&line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {} is no run: {}", runs.len() + 1, e),
+                )
+            })?;
+            runs.push(run);
+        }
+        complete_length += read as u64;
+    }
+    Ok(RecoveredNdjson {
+        runs,
+        path: path.to_path_buf(),
+        complete_length: Some(complete_length),
+    })
+}
+
+/// The version of the MessagePack result format, increased whenever older
+/// readers cannot read the files anymore.
+pub const RESULT_FORMAT_VERSION: u32 = 1;
+
+/// Marks a MessagePack result file.
+const MESSAGE_PACK_MAGIC: &[u8; 4] = b"CJAR";
+
+/// The first bytes of every zstd frame.
+const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Describes how the runs of a MessagePack result file were generated.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ResultHeader {
+    pub format_version: u32,
+    /// The command that wrote the file, e.g. `cja auto`.
+    pub generator: String,
+    pub crate_version: String,
+    pub parameters: BTreeMap<String, String>,
+}
+
+impl ResultHeader {
+    pub fn new(generator: &str, parameters: BTreeMap<String, String>) -> ResultHeader {
+        ResultHeader {
+            format_version: RESULT_FORMAT_VERSION,
+            generator: generator.to_string(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            parameters,
+        }
+    }
+}
+
+/// Writes runs in the MessagePack result format: a magic number, the header
+/// and one MessagePack map per run. Runs are written with their field names,
+/// so fields added with a default value can still be read.
+pub struct MessagePackWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> MessagePackWriter<W> {
+    pub fn new(
+        mut writer: W,
+        header: &ResultHeader,
+    ) -> Result<MessagePackWriter<W>, Box<dyn Error>> {
+        writer.write_all(MESSAGE_PACK_MAGIC)?;
+        rmp_serde::encode::write_named(&mut writer, header)?;
+        Ok(MessagePackWriter { writer })
+    }
+
+    pub fn write_run(&mut self, run: &Run) -> Result<(), Box<dyn Error>> {
+        rmp_serde::encode::write_named(&mut self.writer, run)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a result file in any format written by `cja`: a JSON array, newline
+/// delimited JSON or MessagePack, optionally compressed with zstd. Only
+/// MessagePack files have a header.
+pub fn read_results<R: Read>(
+    reader: R,
+) -> Result<(Option<ResultHeader>, Vec<Run>), Box<dyn Error>> {
+    read_boxed_results(Box::new(reader))
+}
+
+fn read_boxed_results(
+    mut reader: Box<dyn Read + '_>,
+) -> Result<(Option<ResultHeader>, Vec<Run>), Box<dyn Error>> {
+    // A single read may return fewer bytes than the magic numbers, e.g. from a
+    // pipe, so read until there are enough or the input ends.
+    let mut start = Vec::with_capacity(MESSAGE_PACK_MAGIC.len());
+    reader
+        .by_ref()
+        .take(MESSAGE_PACK_MAGIC.len() as u64)
+        .read_to_end(&mut start)?;
+    if start.starts_with(ZSTD_MAGIC) {
+        return read_zstd_results(BufReader::new(io::Cursor::new(start).chain(reader)));
+    }
+    if start.starts_with(MESSAGE_PACK_MAGIC) {
+        let mut reader = BufReader::new(reader);
+        let header: ResultHeader = rmp_serde::from_read(&mut reader)?;
+        if header.format_version > RESULT_FORMAT_VERSION {
+            return Err(format!(
+                "Result format version {} is newer than the supported version {}",
+                header.format_version, RESULT_FORMAT_VERSION
+            )
+            .into());
+        }
+        let mut runs = Vec::new();
+        while !reader.fill_buf()?.is_empty() {
+            runs.push(rmp_serde::from_read(&mut reader)?);
+        }
+        return Ok((Some(header), runs));
+    }
+    let mut input = String::new();
+    io::Cursor::new(start)
+        .chain(reader)
+        .read_to_string(&mut input)?;
+    Ok((None, parse_runs(&input)?))
+}
+
+#[cfg(feature = "zstd")]
+fn read_zstd_results<R: BufRead>(
+    reader: R,
+) -> Result<(Option<ResultHeader>, Vec<Run>), Box<dyn Error>> {
+    read_boxed_results(Box::new(zstd::Decoder::with_buffer(reader)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn read_zstd_results<R: BufRead>(_: R) -> Result<(Option<ResultHeader>, Vec<Run>), Box<dyn Error>> {
+    Err("Result file is compressed with zstd, rebuild with the zstd feature to read it".into())
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+use super::*;
+
+fn fixture() -> Run {
+    serde_json::from_str(include_str!("../partition/result-none-t-3-s-2-r-1.json"))
+        .expect("Invalid json in input")
+}
+
+#[test]
+fn test_parse_runs() {
+    let run = fixture();
+    let array = serde_json::to_string(&vec![&run, &run]).unwrap();
+    assert_eq!(parse_runs(&array).unwrap().len(), 2);
+
+    let mut ndjson = Vec::new();
+    {
+        let mut writer = NdjsonWriter::new(&mut ndjson);
+        writer.write_run(&run).unwrap();
+        writer.write_run(&run).unwrap();
+    }
+    let ndjson = String::from_utf8(ndjson).unwrap();
+    assert_eq!(ndjson.lines().count(), 2);
+    let runs = parse_runs(&ndjson).unwrap();
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[1].in_coins, run.in_coins);
+    assert!(parse_runs("").unwrap().is_empty());
+}
+
+#[test]
+fn test_recover_ndjson() {
+    let path = env::temp_dir().join(format!("cja-test-recover-{}.ndjson", std::process::id()));
+    let missing = recover_ndjson(&path).unwrap();
+    assert!(missing.runs.is_empty());
+    missing.truncate().unwrap();
+    assert!(!path.exists());
+    let line = serde_json::to_string(&fixture()).unwrap();
+    let complete = format!("{}\n{}\n", line, line);
+    let partial = format!("{}{}", complete, &line[..line.len() / 2]);
+    fs::write(&path, &partial).unwrap();
+    let recovered = recover_ndjson(&path).unwrap();
+    assert_eq!(recovered.runs.len(), 2);
+    // Reading leaves the file alone until the caller decides to append.
+    assert_eq!(fs::read_to_string(&path).unwrap(), partial);
+    recovered.truncate().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), complete);
+    // A broken line before complete runs is not just an interrupted write.
+    let corrupt = format!("{}\n{}\n{}\n", line, &line[..line.len() / 2], line);
+    fs::write(&path, &corrupt).unwrap();
+    match recover_ndjson(&path) {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        Ok(_) => panic!("Recovered a file with a broken line"),
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), corrupt);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_message_pack_results() {
+    let run = fixture();
+    let mut parameters = BTreeMap::new();
+    parameters.insert("runs".to_string(), "2".to_string());
+    let header = ResultHeader::new("cja auto", parameters);
+    let mut writer = MessagePackWriter::new(Vec::new(), &header).unwrap();
+    writer.write_run(&run).unwrap();
+    writer.write_run(&run).unwrap();
+    let bytes = writer.into_inner();
+    let json = serde_json::to_string(&vec![&run, &run]).unwrap();
+    assert!(bytes.len() < json.len());
+
+    let (read_header, runs) = read_results(&bytes[..]).unwrap();
+    assert_eq!(read_header, Some(header));
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[1].partition_tuples, run.partition_tuples);
+
+    let (read_header, runs) = read_results(json.as_bytes()).unwrap();
+    assert_eq!(read_header, None);
+    assert_eq!(runs.len(), 2);
+}
+
+/// Returns a single byte per read, like a slow pipe.
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> Read for ByteReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.0[0];
+        self.0 = &self.0[1..];
+        Ok(1)
+    }
+}
+
+#[test]
+fn test_read_results_in_small_reads() {
+    let header = ResultHeader::new("cja auto", BTreeMap::new());
+    let mut writer = MessagePackWriter::new(Vec::new(), &header).unwrap();
+    writer.write_run(&fixture()).unwrap();
+    let bytes = writer.into_inner();
+    let (read_header, runs) = read_results(ByteReader(&bytes)).unwrap();
+    assert_eq!(read_header, Some(header));
+    assert_eq!(runs.len(), 1);
+    let (read_header, runs) = read_results(ByteReader(b"[]")).unwrap();
+    assert_eq!((read_header, runs.len()), (None, 0));
+}
+
+#[test]
+fn test_newer_format_version() {
+    let mut header = ResultHeader::new("cja auto", BTreeMap::new());
+    header.format_version = RESULT_FORMAT_VERSION + 1;
+    let bytes = MessagePackWriter::new(Vec::new(), &header)
+        .unwrap()
+        .into_inner();
+    assert!(read_results(&bytes[..]).is_err());
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_results() {
+    let header = ResultHeader::new("cja auto", BTreeMap::new());
+    let encoder = zstd::Encoder::new(Vec::new(), 0).unwrap();
+    let mut writer = MessagePackWriter::new(encoder, &header).unwrap();
+    writer.write_run(&fixture()).unwrap();
+    let bytes = writer.into_inner().finish().unwrap();
+    let (_, runs) = read_results(&bytes[..]).unwrap();
+    assert_eq!(runs.len(), 1);
+}
+
+#[test]
+fn test_chain_run_fields() {
+    let mut run = fixture();
+    assert_eq!((run.txid.clone(), run.height), (None, None));
+    assert!(!serde_json::to_string(&run).unwrap().contains("txid"));
+    run.txid = Some("ab".repeat(32));
+    run.height = Some(570_000);
+    let mut buffer = Vec::new();
+    {
+        let header = ResultHeader::new("cja chain", BTreeMap::new());
+        let mut writer = MessagePackWriter::new(&mut buffer, &header).unwrap();
+        writer.write_run(&run).unwrap();
+    }
+    let (_, runs) = read_results(&buffer[..]).unwrap();
+    assert_eq!(runs[0].txid, run.txid);
+    assert_eq!(runs[0].height, Some(570_000));
+    let json = serde_json::to_string(&run).unwrap();
+    assert_eq!(parse_runs(&json).unwrap()[0].txid, run.txid);
+}
@@ -0,0 +1,572 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+use rand::distributions::{IndependentSample, LogNormal, Normal};
+use rand::{random, thread_rng, Open01, Rng};
+
+use distribution::{read_distribution, Distribution};
+use shapes::ShapeSampler;
+use types::{Set, Transaction};
+use validation::parse_amount;
+
+#[cfg(test)]
+mod test;
+
+fn realize_subsum(v: &[u64], sum: u64) -> Vec<u64> {
+    let mut d = sum;
+    v.iter()
+        .flat_map(|&o| {
+            if d == 0 {
+                vec![o]
+            } else if o <= d {
+                d -= o;
+                vec![o]
+            } else if o > d {
+                let r = vec![o - d, d];
+                d = 0;
+                r
+            } else {
+                panic!("Universe is collapsing")
+            }
+        })
+        .collect()
+}
+
+/// A source of random coin amounts from which CoinJoin transactions are
+/// generated.
+pub trait CoinSampler: Sync {
+    /// Draws the amount of a coin, always more than zero satoshis.
+    fn random_coin(&self) -> u64;
+
+    fn random_coinjoin_transaction(
+        &self,
+        num_transactions: u64,
+        transaction_size: u64,
+    ) -> (Vec<Transaction>, Set, Set) {
+        let mut transactions: Vec<Transaction> = Vec::new();
+        let mut in_coins: Set = self.random_set(transaction_size);
+        let mut out_coins: Set = self.output_pair(&in_coins);
+        transactions.push(Transaction::new(in_coins.clone(), out_coins.clone()));
+        for _ in 1..num_transactions {
+            let mut new_in = self.random_set(transaction_size);
+            let mut new_out = self.output_pair(&new_in);
+            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
+            in_coins.append(&mut new_in);
+            out_coins.append(&mut new_out);
+        }
+        (transactions, in_coins, out_coins)
+    }
+
+    fn random_coinjoin_transaction_shuffled(
+        &self,
+        num_transactions: u64,
+        transaction_size: u64,
+    ) -> (Vec<Transaction>, Set, Set) {
+        let mut transactions: Vec<Transaction> = Vec::new();
+        let mut in_coins: Set = self.random_set(transaction_size);
+        let mut out_coins: Set = self.output_pair(&in_coins);
+        transactions.push(Transaction::new(in_coins.clone(), out_coins.clone()));
+        for _ in 1..num_transactions {
+            let mut new_in = self.random_set(transaction_size);
+            let mut new_out = self.output_pair(&new_in);
+            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
+            let diff: i64 =
+                new_out.iter().sum::<u64>() as i64 - out_coins.iter().sum::<u64>() as i64;
+            if diff > 0 {
+                new_out = realize_subsum(&new_out, diff as u64)
+            } else if diff < 0 {
+                out_coins = realize_subsum(&out_coins, -diff as u64)
+            };
+            in_coins.append(&mut new_in);
+            out_coins.append(&mut new_out);
+        }
+        (transactions, in_coins, out_coins)
+    }
+
+    fn random_coinjoin_transaction_input_shuffled(
+        &self,
+        num_transactions: u64,
+        transaction_size: u64,
+    ) -> (Vec<Transaction>, Set, Set) {
+        let mut transactions: Vec<Transaction> = Vec::new();
+        let mut in_coins: Set = self.random_set(transaction_size);
+        let mut out_coins: Set = self.output_pair(&in_coins);
+        transactions.push(Transaction::new(in_coins.clone(), out_coins.clone()));
+        for _ in 1..num_transactions {
+            let mut new_in = self.random_set(transaction_size);
+            let mut new_out = self.output_pair(&new_in);
+            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
+            in_coins.append(&mut new_in);
+            thread_rng().shuffle(&mut in_coins);
+            let mut random_in_sum: u64 = in_coins.iter().take(transaction_size as usize).sum();
+            while random_in_sum >= new_out.iter().sum() && random_in_sum >= out_coins.iter().sum() {
+                thread_rng().shuffle(&mut in_coins);
+                random_in_sum = in_coins.iter().take(transaction_size as usize).sum();
+            }
+            if random_in_sum < new_out.iter().sum() {
+                new_out = realize_subsum(&new_out, random_in_sum)
+            } else if random_in_sum < out_coins.iter().sum() {
+                out_coins = realize_subsum(&out_coins, random_in_sum)
+            };
+            out_coins.append(&mut new_out);
+        }
+        (transactions, in_coins, out_coins)
+    }
+
+    fn random_coinjoin_transaction_distributed_shuffled(
+        &self,
+        num_transactions: u64,
+        transaction_size: u64,
+    ) -> (Vec<Transaction>, Set, Set) {
+        let mut transactions: Vec<Transaction> = Vec::new();
+        let mut in_coins: Set = Vec::new();
+        let mut out_sets: Vec<Set> = Vec::new();
+        for _ in 0..num_transactions {
+            let mut new_in = self.random_set(transaction_size);
+            let new_out = self.output_pair(&new_in);
+            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
+            out_sets.push(new_out);
+            in_coins.append(&mut new_in);
+        }
+        let out_coins: Set = out_sets
+            .iter()
+            .flat_map(|out_set| {
+                thread_rng().shuffle(&mut in_coins);
+                let mut random_in_sum: u64 = 0;
+                let out_sum: u64 = out_set.iter().sum();
+                for &coin in in_coins.iter() {
+                    if random_in_sum + coin <= out_sum {
+                        random_in_sum += coin
+                    }
+                }
+                realize_subsum(out_set, random_in_sum)
+            })
+            .collect();
+        (transactions, in_coins, out_coins)
+    }
+
+    /// Like `random_coinjoin_transaction`, but the numbers of inputs and
+    /// outputs of each sub-transaction are drawn from `shapes`.
+    fn random_coinjoin_transaction_with_shapes(
+        &self,
+        num_transactions: u64,
+        shapes: &ShapeSampler,
+    ) -> (Vec<Transaction>, Set, Set) {
+        let mut transactions: Vec<Transaction> = Vec::new();
+        let mut in_coins: Set = Vec::new();
+        let mut out_coins: Set = Vec::new();
+        for _ in 0..num_transactions {
+            let (num_inputs, num_outputs) = shapes.random_shape();
+            let mut new_in = self.random_set(num_inputs);
+            let mut new_out = self.random_outputs(&new_in, num_outputs);
+            transactions.push(Transaction::new(new_in.clone(), new_out.clone()));
+            in_coins.append(&mut new_in);
+            out_coins.append(&mut new_out);
+        }
+        (transactions, in_coins, out_coins)
+    }
+
+    fn random_set(&self, n: u64) -> Set {
+        (0..n).map(|_| self.random_coin()).collect()
+    }
+
+    /// Splits the sum of `s` into `n` outputs, at most one per satoshi. All
+    /// but the last output are drawn from the sampler as long as they fit.
+    fn random_outputs(&self, s: &[u64], n: u64) -> Vec<u64> {
+        let sum: u64 = s.iter().sum();
+        let n = n.min(sum).max(1);
+        let mut outputs = Vec::with_capacity(n as usize);
+        let mut remaining = sum;
+        for left in (1..n).rev() {
+            // Leave at least one satoshi for each of the outputs left.
+            let limit = remaining - left;
+            let coin = (0..100)
+                .map(|_| self.random_coin())
+                .find(|&coin| coin <= limit)
+                .unwrap_or_else(|| thread_rng().gen_range(1, limit + 1));
+            outputs.push(coin);
+            remaining -= coin;
+        }
+        outputs.push(remaining);
+        outputs
+    }
+
+    /// Splits the sum of `s` into two outputs, the first drawn from the
+    /// sampler. Falls back to a uniform split like `random_outputs` if the
+    /// sampler keeps drawing amounts that don't fit.
+    fn output_pair(&self, s: &[u64]) -> Vec<u64> {
+        self.random_outputs(s, 2)
+    }
+}
+
+/// Samples a `Distribution` without flattening its spikes: the recorded point
+/// masses are drawn with their exact amount and the remaining mass of each
+/// bucket is smoothed with a Gaussian kernel on the logarithm of the amount.
+/// Linear interpolation as in `Distribution::random_coin` spreads round
+/// amounts over their bucket instead, which understates how often coins of
+/// different users have equal amounts.
+pub struct SmoothedDistributionSampler {
+    point_values: Vec<u64>,
+    /// Cumulative share of the point masses, up to their total share.
+    point_cumulative: Vec<f64>,
+    /// Probability of drawing a point mass.
+    point_mass: f64,
+    coins: Vec<u64>,
+    /// Cumulative distribution of the coins without the point masses.
+    cumulative: Vec<f64>,
+    kernel: Normal,
+}
+
+impl SmoothedDistributionSampler {
+    /// `bandwidth` is the standard deviation of the kernel in log space, so
+    /// amounts typically deviate from their bucket by a factor of about
+    /// `1 + bandwidth`. Fails for an empty distribution, a negative bandwidth
+    /// and if the point masses leave no coins to smooth.
+    pub fn new(
+        distribution: &Distribution,
+        bandwidth: f64,
+    ) -> Result<SmoothedDistributionSampler, String> {
+        let points = &distribution.cumulative_normalized;
+        if points.is_empty() {
+            return Err("Cannot sample an empty distribution".to_string());
+        }
+        if bandwidth.is_nan() || bandwidth < 0f64 {
+            return Err(format!("Invalid smoothing bandwidth {}", bandwidth));
+        }
+        let mut masses: Vec<f64> = Vec::with_capacity(points.len());
+        let mut previous = 0f64;
+        for &(_, probability) in points.iter() {
+            masses.push(probability - previous);
+            previous = probability;
+        }
+        let mut point_values = Vec::new();
+        let mut point_cumulative = Vec::new();
+        let mut point_mass = 0f64;
+        for &(value, mass) in distribution.point_masses.iter() {
+            // The bucket of an amount is the last point at or below it.
+            let bucket = points.partition_point(|&(coin, _)| coin <= value);
+            if bucket > 0 {
+                masses[bucket - 1] = (masses[bucket - 1] - mass).max(0f64);
+            }
+            point_mass += mass;
+            point_values.push(value);
+            point_cumulative.push(point_mass);
+        }
+        let remainder: f64 = masses.iter().sum();
+        if remainder.is_nan() || remainder <= 0f64 {
+            return Err(
+                "The point masses of the distribution leave no coins to smooth".to_string(),
+            );
+        }
+        let mut cumulative = Vec::with_capacity(masses.len());
+        let mut sum = 0f64;
+        for mass in masses {
+            sum += mass;
+            cumulative.push(sum / remainder);
+        }
+        Ok(SmoothedDistributionSampler {
+            point_values,
+            point_cumulative,
+            point_mass: point_mass / (point_mass + remainder),
+            coins: points.iter().map(|&(coin, _)| coin).collect(),
+            cumulative,
+            kernel: Normal::new(0f64, bandwidth),
+        })
+    }
+}
+
+impl CoinSampler for SmoothedDistributionSampler {
+    fn random_coin(&self) -> u64 {
+        let Open01(rand) = random::<Open01<f64>>();
+        if rand < self.point_mass {
+            let total = self.point_cumulative[self.point_cumulative.len() - 1];
+            let target = rand / self.point_mass * total;
+            let index = self
+                .point_cumulative
+                .partition_point(|&p| p < target)
+                .min(self.point_values.len() - 1);
+            return self.point_values[index];
+        }
+        let Open01(rand) = random::<Open01<f64>>();
+        let index = self
+            .cumulative
+            .partition_point(|&p| p < rand)
+            .min(self.coins.len() - 1);
+        let center = self.coins[index].max(1) as f64;
+        loop {
+            let coin = (center * self.kernel.ind_sample(&mut thread_rng()).exp()).round();
+            if coin >= 1f64 && coin < u64::MAX as f64 {
+                return coin as u64;
+            }
+        }
+    }
+}
+
+/// Log-normally distributed amounts, `mean` and `std_dev` are those of the
+/// natural logarithm of the amount in satoshis.
+pub struct LogNormalSampler {
+    distribution: LogNormal,
+}
+
+impl LogNormalSampler {
+    pub fn new(mean: f64, std_dev: f64) -> Result<LogNormalSampler, String> {
+        if !mean.is_finite() || !std_dev.is_finite() || std_dev < 0f64 {
+            return Err(format!(
+                "Invalid log-normal parameters, mean {} and standard deviation {}",
+                mean, std_dev
+            ));
+        }
+        Ok(LogNormalSampler {
+            distribution: LogNormal::new(mean, std_dev),
+        })
+    }
+}
+
+impl CoinSampler for LogNormalSampler {
+    fn random_coin(&self) -> u64 {
+        loop {
+            let coin = self.distribution.ind_sample(&mut thread_rng()).round();
+            if coin >= 1f64 && coin < u64::MAX as f64 {
+                return coin as u64;
+            }
+        }
+    }
+}
+
+/// Amounts distributed uniformly between `min` and `max`, both inclusive.
+pub struct UniformSampler {
+    min: u64,
+    max: u64,
+}
+
+impl UniformSampler {
+    pub fn new(min: u64, max: u64) -> Result<UniformSampler, String> {
+        if min == 0 || min > max || max == u64::MAX {
+            return Err(format!("Invalid range of amounts {} to {}", min, max));
+        }
+        Ok(UniformSampler { min, max })
+    }
+}
+
+impl CoinSampler for UniformSampler {
+    fn random_coin(&self) -> u64 {
+        thread_rng().gen_range(self.min, self.max + 1)
+    }
+}
+
+/// Rounds the amounts of another sampler to the closest multiple of `unit`
+/// with the given `probability`, like users who pay round amounts.
+pub struct RoundBiasedSampler {
+    sampler: Box<dyn CoinSampler>,
+    unit: u64,
+    probability: f64,
+}
+
+impl RoundBiasedSampler {
+    pub fn new(
+        sampler: Box<dyn CoinSampler>,
+        unit: u64,
+        probability: f64,
+    ) -> Result<RoundBiasedSampler, String> {
+        if unit == 0 {
+            return Err("The unit of round amounts must be positive".to_string());
+        }
+        if !(0f64..=1f64).contains(&probability) {
+            return Err(format!(
+                "The probability of round amounts must be between 0 and 1, not {}",
+                probability
+            ));
+        }
+        Ok(RoundBiasedSampler {
+            sampler,
+            unit,
+            probability,
+        })
+    }
+}
+
+impl CoinSampler for RoundBiasedSampler {
+    fn random_coin(&self) -> u64 {
+        let coin = self.sampler.random_coin();
+        let Open01(rand) = random::<Open01<f64>>();
+        if rand >= self.probability {
+            return coin;
+        }
+        let rounded = coin.saturating_add(self.unit / 2) / self.unit * self.unit;
+        rounded.max(self.unit)
+    }
+}
+
+/// Draws each amount from one of several samplers, chosen with probability
+/// proportional to its weight.
+pub struct MixtureSampler {
+    cumulative_weights: Vec<f64>,
+    samplers: Vec<Box<dyn CoinSampler>>,
+}
+
+impl MixtureSampler {
+    pub fn new(components: Vec<(f64, Box<dyn CoinSampler>)>) -> Result<MixtureSampler, String> {
+        let mut total = 0f64;
+        let mut cumulative_weights = Vec::new();
+        let mut samplers = Vec::new();
+        for (weight, sampler) in components {
+            if weight.is_nan() || weight < 0f64 {
+                return Err(format!("Invalid mixture weight {}", weight));
+            }
+            total += weight;
+            cumulative_weights.push(total);
+            samplers.push(sampler);
+        }
+        if total <= 0f64 {
+            return Err("A mixture needs a component with positive weight".to_string());
+        }
+        Ok(MixtureSampler {
+            cumulative_weights: cumulative_weights.iter().map(|w| w / total).collect(),
+            samplers,
+        })
+    }
+}
+
+impl CoinSampler for MixtureSampler {
+    fn random_coin(&self) -> u64 {
+        let Open01(rand) = random::<Open01<f64>>();
+        let index = self
+            .cumulative_weights
+            .partition_point(|&weight| weight < rand)
+            .min(self.samplers.len() - 1);
+        self.samplers[index].random_coin()
+    }
+}
+
+/// Draws amounts uniformly from a list, e.g. the UTXOs of a wallet.
+pub struct AmountsSampler {
+    amounts: Vec<u64>,
+}
+
+impl AmountsSampler {
+    pub fn new(amounts: Vec<u64>) -> Result<AmountsSampler, String> {
+        let amounts: Vec<u64> = amounts.into_iter().filter(|&a| a > 0).collect();
+        if amounts.is_empty() {
+            return Err("No amounts of value to sample from".to_string());
+        }
+        Ok(AmountsSampler { amounts })
+    }
+
+    /// Reads amounts from the first column of a CSV file, in satoshis or in
+    /// bitcoin with a decimal point. Empty lines, lines starting with `#` and
+    /// a first line without digits, the header, are skipped.
+    pub fn from_csv<R: Read>(reader: R) -> Result<AmountsSampler, String> {
+        let mut amounts = Vec::new();
+        for (number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let field = line.split(',').next().unwrap_or_default().trim();
+            match parse_amount(field) {
+                Ok(amount) => amounts.push(amount),
+                Err(_) if number == 0 && !line.chars().any(|c| c.is_ascii_digit()) => continue,
+                Err(e) => {
+                    return Err(format!(
+                        "Invalid amount {} on line {}: {}",
+                        field,
+                        number + 1,
+                        e
+                    ))
+                }
+            }
+        }
+        AmountsSampler::new(amounts)
+    }
+}
+
+impl CoinSampler for AmountsSampler {
+    fn random_coin(&self) -> u64 {
+        self.amounts[thread_rng().gen_range(0, self.amounts.len())]
+    }
+}
+
+/// A serializable description of a sampler, e.g.
+/// `{"type": "log_normal", "mean": 14.0, "std_dev": 3.0}`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SamplerSpec {
+    /// A distribution file written by `build_distribution`. With `smoothing`
+    /// it is sampled by a `SmoothedDistributionSampler` with that bandwidth.
+    Distribution {
+        path: String,
+        #[serde(default)]
+        smoothing: Option<f64>,
+    },
+    /// A CSV file of amounts, see `AmountsSampler::from_csv`.
+    Amounts {
+        path: String,
+    },
+    LogNormal {
+        mean: f64,
+        std_dev: f64,
+    },
+    Uniform {
+        min: u64,
+        max: u64,
+    },
+    RoundBiased {
+        sampler: Box<SamplerSpec>,
+        unit: u64,
+        probability: f64,
+    },
+    Mixture {
+        components: Vec<MixtureComponent>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MixtureComponent {
+    pub weight: f64,
+    pub sampler: SamplerSpec,
+}
+
+impl SamplerSpec {
+    /// Creates the described sampler, reading any files it refers to.
+    pub fn build(&self) -> Result<Box<dyn CoinSampler>, Box<dyn Error>> {
+        Ok(match *self {
+            SamplerSpec::Distribution {
+                ref path,
+                smoothing,
+            } => {
+                let distribution = read_distribution(File::open(path)?)?;
+                match smoothing {
+                    Some(bandwidth) => {
+                        Box::new(SmoothedDistributionSampler::new(&distribution, bandwidth)?)
+                    }
+                    None => Box::new(distribution),
+                }
+            }
+            SamplerSpec::Amounts { ref path } => {
+                Box::new(AmountsSampler::from_csv(File::open(path)?)?)
+            }
+            SamplerSpec::LogNormal { mean, std_dev } => {
+                Box::new(LogNormalSampler::new(mean, std_dev)?)
+            }
+            SamplerSpec::Uniform { min, max } => Box::new(UniformSampler::new(min, max)?),
+            SamplerSpec::RoundBiased {
+                ref sampler,
+                unit,
+                probability,
+            } => Box::new(RoundBiasedSampler::new(
+                sampler.build()?,
+                unit,
+                probability,
+            )?),
+            SamplerSpec::Mixture { ref components } => {
+                let mut built = Vec::new();
+                for component in components {
+                    built.push((component.weight, component.sampler.build()?));
+                }
+                Box::new(MixtureSampler::new(built)?)
+            }
+        })
+    }
+}
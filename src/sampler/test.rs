@@ -0,0 +1,240 @@
+use rmp_serde;
+use serde_json;
+
+use super::*;
+use shapes::ShapeHistogram;
+
+#[test]
+fn test_uniform_sampler() {
+    let sampler = UniformSampler::new(10, 12).unwrap();
+    for _ in 0..100 {
+        let coin = sampler.random_coin();
+        assert!(coin >= 10 && coin <= 12);
+    }
+    assert!(UniformSampler::new(0, 12).is_err());
+    assert!(UniformSampler::new(13, 12).is_err());
+}
+
+#[test]
+fn test_round_biased_sampler() {
+    let sampler =
+        RoundBiasedSampler::new(Box::new(UniformSampler::new(1, 1_000).unwrap()), 100, 1.0)
+            .unwrap();
+    for _ in 0..100 {
+        let coin = sampler.random_coin();
+        assert!(coin % 100 == 0 && coin > 0);
+    }
+    assert!(
+        RoundBiasedSampler::new(Box::new(UniformSampler::new(1, 1_000).unwrap()), 0, 1.0).is_err()
+    );
+    for &probability in &[-0.1, 1.5, f64::NAN] {
+        assert!(RoundBiasedSampler::new(
+            Box::new(UniformSampler::new(1, 1_000).unwrap()),
+            100,
+            probability
+        )
+        .is_err());
+    }
+    let huge =
+        RoundBiasedSampler::new(Box::new(ConstantSampler(u64::max_value())), 100, 1.0).unwrap();
+    assert_eq!(huge.random_coin(), u64::max_value() / 100 * 100);
+}
+
+struct ConstantSampler(u64);
+
+impl CoinSampler for ConstantSampler {
+    fn random_coin(&self) -> u64 {
+        self.0
+    }
+}
+
+#[test]
+fn test_log_normal_sampler() {
+    let sampler = LogNormalSampler::new(10.0, 1.0).unwrap();
+    for _ in 0..100 {
+        assert!(sampler.random_coin() > 0);
+    }
+    assert!(LogNormalSampler::new(10.0, -1.0).is_err());
+    assert!(LogNormalSampler::new(f64::NAN, 1.0).is_err());
+}
+
+#[test]
+fn test_output_pair() {
+    // No amount drawn from this sampler fits below the sum of the inputs.
+    let sampler = UniformSampler::new(1_000, 2_000).unwrap();
+    for _ in 0..100 {
+        let outputs = sampler.output_pair(&[10]);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs.iter().sum::<u64>(), 10);
+        assert!(outputs.iter().all(|&coin| coin > 0));
+    }
+}
+
+#[test]
+fn test_mixture_sampler() {
+    let sampler = MixtureSampler::new(vec![
+        (
+            0.0,
+            Box::new(UniformSampler::new(1, 1).unwrap()) as Box<dyn CoinSampler>,
+        ),
+        (2.0, Box::new(UniformSampler::new(5, 5).unwrap())),
+    ])
+    .unwrap();
+    for _ in 0..100 {
+        assert_eq!(sampler.random_coin(), 5);
+    }
+    assert!(MixtureSampler::new(vec![]).is_err());
+}
+
+#[test]
+fn test_amounts_from_csv() {
+    let csv = "amount,address\n150000,a\n\n# comment\n0.5,b\n0,c\n";
+    let sampler = AmountsSampler::from_csv(csv.as_bytes()).unwrap();
+    assert_eq!(sampler.amounts, vec![150_000, 50_000_000]);
+    for _ in 0..100 {
+        let coin = sampler.random_coin();
+        assert!(coin == 150_000 || coin == 50_000_000);
+    }
+    assert!(AmountsSampler::from_csv("1\nx\n".as_bytes()).is_err());
+    assert!(AmountsSampler::from_csv("amount\n".as_bytes()).is_err());
+    // A typo in the first amount is no header.
+    assert!(AmountsSampler::from_csv("15O000\n2000\n".as_bytes()).is_err());
+}
+
+#[test]
+fn test_smoothed_distribution_sampler() {
+    // Half of the coins are exactly 10000, the rest is spread over buckets.
+    let distribution = Distribution::with_point_masses(
+        vec![(5_000, 0.25), (10_000, 0.75), (20_000, 1.0)],
+        vec![(10_000, 0.5)],
+    );
+    let sampler = SmoothedDistributionSampler::new(&distribution, 0.05).unwrap();
+    assert!((sampler.point_mass - 0.5).abs() < 1e-9);
+    assert_eq!(sampler.cumulative, vec![0.5, 0.5, 1.0]);
+    let exact = (0..1_000)
+        .filter(|_| sampler.random_coin() == 10_000)
+        .count();
+    assert!(exact > 400 && exact < 600);
+    let unsmoothed = SmoothedDistributionSampler::new(&distribution, 0.0).unwrap();
+    for _ in 0..100 {
+        let coin = unsmoothed.random_coin();
+        assert!(coin == 5_000 || coin == 10_000 || coin == 20_000);
+    }
+}
+
+#[test]
+fn test_smoothed_distribution_without_point_masses() {
+    let distribution = Distribution::new(vec![(100, 0.5), (200, 1.0)]);
+    let sampler = SmoothedDistributionSampler::new(&distribution, 0.0).unwrap();
+    assert_eq!(sampler.point_mass, 0.0);
+    for _ in 0..100 {
+        let coin = sampler.random_coin();
+        assert!(coin == 100 || coin == 200);
+    }
+}
+
+#[test]
+fn test_invalid_smoothed_distribution_sampler() {
+    let distribution = Distribution::new(vec![(100, 0.5), (200, 1.0)]);
+    for &bandwidth in &[-0.1, f64::NAN] {
+        assert!(SmoothedDistributionSampler::new(&distribution, bandwidth).is_err());
+    }
+    assert!(SmoothedDistributionSampler::new(&Distribution::new(Vec::new()), 0.05).is_err());
+    // All coins of the only bucket are a point mass.
+    let points_only = Distribution::with_point_masses(vec![(100, 1.0)], vec![(100, 1.0)]);
+    assert!(SmoothedDistributionSampler::new(&points_only, 0.05).is_err());
+}
+
+#[test]
+fn test_distribution_without_point_masses_field() {
+    // Distribution files written before point masses were recorded.
+    let old = rmp_serde::to_vec(&(vec![(100u64, 1.0f64)],)).unwrap();
+    let distribution: Distribution = rmp_serde::from_slice(&old).unwrap();
+    assert!(distribution.point_masses.is_empty());
+    assert_eq!(distribution.cumulative_normalized, vec![(100, 1.0)]);
+}
+
+#[test]
+fn test_read_distribution() {
+    let distribution =
+        Distribution::with_point_masses(vec![(100, 0.5), (200, 1.0)], vec![(100, 0.5)]);
+    let json = serde_json::to_vec(&distribution).unwrap();
+    let msgpack = rmp_serde::to_vec(&distribution).unwrap();
+    for file in vec![json, msgpack] {
+        let read = read_distribution(file.as_slice()).unwrap();
+        assert_eq!(
+            read.cumulative_normalized,
+            distribution.cumulative_normalized
+        );
+        assert_eq!(read.point_masses, distribution.point_masses);
+    }
+    assert!(read_distribution(&b"{}"[..]).is_err());
+}
+
+#[test]
+fn test_sampler_spec() {
+    let spec: SamplerSpec = serde_json::from_str(
+        r#"{"type": "mixture", "components": [
+            {"weight": 1.0, "sampler": {"type": "log_normal", "mean": 10.0, "std_dev": 1.0}},
+            {"weight": 1.0, "sampler": {"type": "round_biased", "unit": 1000, "probability": 0.5,
+                "sampler": {"type": "uniform", "min": 1000, "max": 100000}}}
+        ]}"#,
+    )
+    .unwrap();
+    let sampler = spec.build().unwrap();
+    assert!(sampler.random_coin() > 0);
+    let invalid = SamplerSpec::Uniform { min: 5, max: 1 };
+    assert!(invalid.build().is_err());
+}
+
+#[test]
+fn test_random_coinjoin_transaction() {
+    let sampler = UniformSampler::new(1_000, 100_000).unwrap();
+    let (transactions, in_coins, out_coins) = sampler.random_coinjoin_transaction(3, 2);
+    assert_eq!(transactions.len(), 3);
+    assert_eq!(in_coins.len(), 6);
+    assert_eq!(out_coins.len(), 6);
+    for transaction in transactions.iter() {
+        assert_eq!(
+            transaction.inputs.iter().sum::<u64>(),
+            transaction.outputs.iter().sum::<u64>()
+        );
+    }
+    let (_, in_coins, out_coins) = sampler.random_coinjoin_transaction_shuffled(3, 2);
+    assert_eq!(in_coins.iter().sum::<u64>(), out_coins.iter().sum::<u64>());
+}
+
+#[test]
+fn test_random_coinjoin_transaction_with_shapes() {
+    let mut histogram = ShapeHistogram::new();
+    histogram.add(1, 3, 1);
+    histogram.add(2, 1, 1);
+    let shapes = ShapeSampler::new(&histogram, 5, 5).unwrap();
+    let sampler = UniformSampler::new(1_000, 100_000).unwrap();
+    let (transactions, in_coins, out_coins) =
+        sampler.random_coinjoin_transaction_with_shapes(4, &shapes);
+    assert_eq!(transactions.len(), 4);
+    for transaction in transactions.iter() {
+        let shape = (
+            transaction.inputs.len() as u64,
+            transaction.outputs.len() as u64,
+        );
+        assert!(shape == (1, 3) || shape == (2, 1));
+        assert_eq!(
+            transaction.inputs.iter().sum::<u64>(),
+            transaction.outputs.iter().sum::<u64>()
+        );
+    }
+    assert_eq!(in_coins.iter().sum::<u64>(), out_coins.iter().sum::<u64>());
+}
+
+#[test]
+fn test_random_outputs() {
+    let sampler = UniformSampler::new(1_000, 100_000).unwrap();
+    // Too small for coins of the sampler.
+    let outputs = sampler.random_outputs(&[10], 4);
+    assert_eq!(outputs.len(), 4);
+    assert_eq!(outputs.iter().sum::<u64>(), 10);
+    assert!(outputs.iter().all(|&coin| coin > 0));
+    assert_eq!(sampler.random_outputs(&[2], 4).len(), 2);
+}
@@ -0,0 +1,189 @@
+use derived::filter_derived_partitions;
+use probability::{part_indices, LinkProbabilities};
+use statistics::Estimate;
+use types::{Partition, Run, Set};
+
+#[cfg(test)]
+mod test;
+
+/// How well the mappings of a run reveal its original sub-transactions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GroundTruthScore {
+    pub mappings: usize,
+    /// Whether the grouping of the original sub-transactions is one of the
+    /// mappings.
+    pub found: bool,
+    /// Rank of the true mapping when mappings are ordered from the finest to
+    /// the coarsest, mappings with the same number of parts share a rank.
+    pub rank: Option<usize>,
+    /// Fraction of the mappings that equal the true mapping.
+    pub probability: f64,
+    /// Fraction of the pairs of inputs of the same participant that are linked
+    /// with probability one. `None` if no participant has several inputs.
+    pub certain_input_links: Option<f64>,
+    /// Fraction of the pairs of an input and an output of the same participant
+    /// that are linked with probability one. `None` if the outputs of the
+    /// CoinJoin are not the original outputs, e.g. because they were shuffled.
+    pub certain_input_output_links: Option<f64>,
+    pub participants: usize,
+    /// Participants whose inputs form a sub-transaction of their own in every
+    /// mapping.
+    pub identified_participants: usize,
+}
+
+impl GroundTruthScore {
+    /// Scores the non-derived mappings of `run`.
+    pub fn new(run: &Run) -> GroundTruthScore {
+        GroundTruthScore::with_mappings(run, &filter_derived_partitions(&run.partition_tuples))
+    }
+
+    /// Scores the given mappings of `run` against its original transactions.
+    pub fn with_mappings(
+        run: &Run,
+        partition_tuples: &[(Partition, Partition)],
+    ) -> GroundTruthScore {
+        let true_inputs: Partition = run
+            .original_transactions
+            .iter()
+            .map(|t| t.inputs.clone())
+            .collect();
+        let true_outputs: Partition = run
+            .original_transactions
+            .iter()
+            .map(|t| t.outputs.clone())
+            .collect();
+        let outputs_unchanged = sorted(run.out_coins.clone())
+            == sorted(true_outputs.iter().flat_map(|set| set.clone()).collect());
+
+        let true_inputs_key = normalize(&true_inputs);
+        let true_outputs_key = normalize(&true_outputs);
+        let is_true = |(in_partition, out_partition): &(Partition, Partition)| {
+            normalize(in_partition) == true_inputs_key
+                && (!outputs_unchanged || normalize(out_partition) == true_outputs_key)
+        };
+        let true_mappings = partition_tuples.iter().filter(|m| is_true(m)).count();
+        let rank = partition_tuples.iter().find(|m| is_true(m)).map(|m| {
+            1 + partition_tuples
+                .iter()
+                .filter(|(in_partition, _)| in_partition.len() > m.0.len())
+                .count()
+        });
+
+        let links = LinkProbabilities::new(&run.in_coins, &run.out_coins, partition_tuples);
+        let in_participants = part_indices(&run.in_coins, &true_inputs);
+        let mut input_links = Vec::new();
+        for i in 0..run.in_coins.len() {
+            for j in (i + 1)..run.in_coins.len() {
+                if in_participants[i] == in_participants[j] {
+                    input_links.push(links.in_in[i][j]);
+                }
+            }
+        }
+        let certain_input_output_links = if outputs_unchanged {
+            let out_participants = part_indices(&run.out_coins, &true_outputs);
+            let mut input_output_links = Vec::new();
+            for (i, in_participant) in in_participants.iter().enumerate() {
+                for (o, out_participant) in out_participants.iter().enumerate() {
+                    if in_participant == out_participant {
+                        input_output_links.push(links.in_out[i][o]);
+                    }
+                }
+            }
+            certain_fraction(&input_output_links)
+        } else {
+            None
+        };
+
+        let identified_participants = true_inputs
+            .iter()
+            .filter(|inputs| {
+                let inputs = sorted(inputs.to_vec());
+                !partition_tuples.is_empty()
+                    && partition_tuples.iter().all(|(in_partition, _)| {
+                        in_partition.iter().any(|set| sorted(set.clone()) == inputs)
+                    })
+            })
+            .count();
+
+        GroundTruthScore {
+            mappings: partition_tuples.len(),
+            found: true_mappings > 0,
+            rank,
+            probability: match partition_tuples.len() {
+                0 => 0f64,
+                n => true_mappings as f64 / n as f64,
+            },
+            certain_input_links: certain_fraction(&input_links),
+            certain_input_output_links,
+            participants: true_inputs.len(),
+            identified_participants,
+        }
+    }
+}
+
+/// Ground truth scores aggregated over runs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScoreSummary {
+    pub runs: usize,
+    pub found: Estimate,
+    pub rank: Estimate,
+    pub probability: Estimate,
+    pub certain_input_links: Estimate,
+    pub certain_input_output_links: Estimate,
+    /// Fraction of the participants of a run that are identified.
+    pub identified_participants: Estimate,
+}
+
+impl ScoreSummary {
+    pub fn new(scores: &[GroundTruthScore]) -> ScoreSummary {
+        let found = scores.iter().filter(|s| s.found).count();
+        let ranks: Vec<f64> = scores
+            .iter()
+            .filter_map(|s| s.rank)
+            .map(|r| r as f64)
+            .collect();
+        let probabilities: Vec<f64> = scores.iter().map(|s| s.probability).collect();
+        let input_links: Vec<f64> = scores
+            .iter()
+            .filter_map(|s| s.certain_input_links)
+            .collect();
+        let input_output_links: Vec<f64> = scores
+            .iter()
+            .filter_map(|s| s.certain_input_output_links)
+            .collect();
+        let identified: Vec<f64> = scores
+            .iter()
+            .filter(|s| s.participants > 0)
+            .map(|s| s.identified_participants as f64 / s.participants as f64)
+            .collect();
+        ScoreSummary {
+            runs: scores.len(),
+            found: Estimate::proportion(found, scores.len()),
+            rank: Estimate::mean(&ranks),
+            probability: Estimate::mean(&probabilities),
+            certain_input_links: Estimate::mean(&input_links),
+            certain_input_output_links: Estimate::mean(&input_output_links),
+            identified_participants: Estimate::mean(&identified),
+        }
+    }
+}
+
+fn certain_fraction(probabilities: &[f64]) -> Option<f64> {
+    match probabilities.len() {
+        0 => None,
+        n => Some(probabilities.iter().filter(|&&p| p == 1f64).count() as f64 / n as f64),
+    }
+}
+
+fn sorted(mut set: Set) -> Set {
+    set.sort();
+    set
+}
+
+/// A representation of a partition that is independent of the order of its
+/// parts and of the coins in them.
+fn normalize(partition: &Partition) -> Vec<Set> {
+    let mut sets: Vec<Set> = partition.iter().map(|set| sorted(set.clone())).collect();
+    sets.sort();
+    sets
+}
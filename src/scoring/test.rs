@@ -0,0 +1,109 @@
+use serde_json;
+
+use super::*;
+use types::Transaction;
+
+fn run(
+    original_transactions: Vec<Transaction>,
+    in_coins: Set,
+    out_coins: Set,
+    partition_tuples: Vec<(Partition, Partition)>,
+) -> Run {
+    Run {
+        num_transactions: original_transactions.len() as u64,
+        num_inputs_per_transaction: 2,
+        shuffled: None,
+        shapes: None,
+        original_transactions,
+        in_coins,
+        out_coins,
+        partition_tuples,
+        pairings: Vec::new(),
+        duration_secs: 0,
+        duration_nano: 0,
+        search_status: Default::default(),
+        txid: None,
+        height: None,
+    }
+}
+
+#[test]
+fn test_score_identified() {
+    let run = run(
+        vec![
+            Transaction::new(vec![1, 2], vec![3]),
+            Transaction::new(vec![10, 20], vec![30]),
+        ],
+        vec![1, 2, 10, 20],
+        vec![3, 30],
+        vec![
+            (vec![vec![2, 1], vec![10, 20]], vec![vec![3], vec![30]]),
+            (vec![vec![1, 2, 10, 20]], vec![vec![3, 30]]),
+        ],
+    );
+    let score = GroundTruthScore::new(&run);
+    assert_eq!(
+        score,
+        GroundTruthScore {
+            mappings: 1,
+            found: true,
+            rank: Some(1),
+            probability: 1.0,
+            certain_input_links: Some(1.0),
+            certain_input_output_links: Some(1.0),
+            participants: 2,
+            identified_participants: 2,
+        }
+    );
+}
+
+#[test]
+fn test_score_ambiguous() {
+    // The inputs 1 and 2 can be swapped, the true mapping is one of two.
+    let run = run(
+        vec![
+            Transaction::new(vec![1, 5], vec![6]),
+            Transaction::new(vec![2, 4], vec![6]),
+        ],
+        vec![1, 5, 2, 4],
+        vec![6, 6],
+        vec![
+            (vec![vec![1, 5], vec![2, 4]], vec![vec![6], vec![6]]),
+            (vec![vec![2, 5], vec![1, 4]], vec![vec![6], vec![6]]),
+            (vec![vec![1, 5, 2, 4]], vec![vec![6, 6]]),
+        ],
+    );
+    let score = GroundTruthScore::with_mappings(&run, &run.partition_tuples[..2]);
+    assert!(score.found);
+    assert_eq!(score.rank, Some(1));
+    assert_eq!(score.probability, 0.5);
+    assert_eq!(score.certain_input_links, Some(0.0));
+    assert_eq!(score.identified_participants, 0);
+}
+
+#[test]
+fn test_score_fixtures() {
+    let test_files = vec![
+        include_str!("../partition/result-none-t-3-s-2-r-1.json"),
+        include_str!("../partition/result-output-t-3-s-2-r-1.json"),
+        include_str!("../partition/result-input-t-3-s-2-r-1.json"),
+    ];
+    let scores: Vec<GroundTruthScore> = test_files
+        .iter()
+        .map(|file| {
+            let run: Run = serde_json::from_str(file).expect("Invalid json in input");
+            GroundTruthScore::new(&run)
+        })
+        .collect();
+    // Without shuffling the original transactions are always a mapping.
+    assert!(scores[0].found);
+    assert!(scores[0].certain_input_output_links.is_some());
+    assert_eq!(scores[1].certain_input_output_links, None);
+    for score in scores.iter() {
+        assert_eq!(score.participants, 3);
+        assert!(score.probability >= 0.0 && score.probability <= 1.0);
+    }
+    let summary = ScoreSummary::new(&scores);
+    assert_eq!(summary.runs, 3);
+    assert!(summary.found.lower <= summary.found.mean && summary.found.mean <= summary.found.upper);
+}
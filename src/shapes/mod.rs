@@ -0,0 +1,106 @@
+use rand::{thread_rng, Rng};
+
+use blockchain::Block;
+
+#[cfg(test)]
+mod test;
+
+/// How many transactions have each number of inputs and outputs, mined from
+/// the chain to generate participants of realistic shapes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ShapeHistogram {
+    /// The number of transactions of each shape as (inputs, outputs, count),
+    /// sorted by shape.
+    pub counts: Vec<(u64, u64, u64)>,
+}
+
+impl ShapeHistogram {
+    pub fn new() -> ShapeHistogram {
+        ShapeHistogram::default()
+    }
+
+    pub fn add(&mut self, inputs: u64, outputs: u64, count: u64) {
+        match self
+            .counts
+            .binary_search_by_key(&(inputs, outputs), |&(inputs, outputs, _)| {
+                (inputs, outputs)
+            }) {
+            Ok(index) => self.counts[index].2 += count,
+            Err(index) => self.counts.insert(index, (inputs, outputs, count)),
+        }
+    }
+
+    /// Adds the shapes of the transactions of a block, except the coinbase.
+    pub fn add_block(&mut self, block: &Block) {
+        for transaction in block.transactions.iter() {
+            if transaction.inputs.iter().any(|input| input.is_coinbase()) {
+                continue;
+            }
+            self.add(
+                transaction.inputs.len() as u64,
+                transaction.outputs.len() as u64,
+                1,
+            );
+        }
+    }
+
+    pub fn merge(&mut self, other: &ShapeHistogram) {
+        for &(inputs, outputs, count) in other.counts.iter() {
+            self.add(inputs, outputs, count);
+        }
+    }
+
+    pub fn count(&self, inputs: u64, outputs: u64) -> u64 {
+        self.counts
+            .iter()
+            .find(|&&(i, o, _)| (i, o) == (inputs, outputs))
+            .map_or(0, |&(_, _, count)| count)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|&(_, _, count)| count).sum()
+    }
+}
+
+/// Draws the numbers of inputs and outputs of participants from a
+/// `ShapeHistogram`. Only shapes with at most `max_inputs` inputs and
+/// `max_outputs` outputs are drawn, since large sub-transactions make the
+/// analysis infeasible.
+pub struct ShapeSampler {
+    shapes: Vec<(u64, u64)>,
+    cumulative: Vec<u64>,
+}
+
+impl ShapeSampler {
+    pub fn new(
+        histogram: &ShapeHistogram,
+        max_inputs: u64,
+        max_outputs: u64,
+    ) -> Result<ShapeSampler, String> {
+        let mut shapes = Vec::new();
+        let mut cumulative = Vec::new();
+        let mut total = 0;
+        for &(inputs, outputs, count) in histogram.counts.iter() {
+            if inputs == 0 || outputs == 0 || inputs > max_inputs || outputs > max_outputs {
+                continue;
+            }
+            total += count;
+            shapes.push((inputs, outputs));
+            cumulative.push(total);
+        }
+        if total == 0 {
+            return Err(format!(
+                "No transactions with at most {} inputs and {} outputs",
+                max_inputs, max_outputs
+            ));
+        }
+        Ok(ShapeSampler { shapes, cumulative })
+    }
+
+    /// Draws the number of inputs and outputs of a participant.
+    pub fn random_shape(&self) -> (u64, u64) {
+        let total = self.cumulative[self.cumulative.len() - 1];
+        let rand = thread_rng().gen_range(0, total);
+        self.shapes[self.cumulative.partition_point(|&count| count <= rand)]
+    }
+}
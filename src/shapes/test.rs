@@ -0,0 +1,38 @@
+use super::*;
+
+#[test]
+fn test_histogram() {
+    let mut histogram = ShapeHistogram::new();
+    histogram.add(2, 2, 1);
+    histogram.add(1, 2, 3);
+    histogram.add(2, 2, 1);
+    let mut other = ShapeHistogram::new();
+    other.add(1, 1, 4);
+    other.add(1, 2, 1);
+    histogram.merge(&other);
+    assert_eq!(histogram.counts, vec![(1, 1, 4), (1, 2, 4), (2, 2, 2)]);
+    assert_eq!(histogram.count(1, 2), 4);
+    assert_eq!(histogram.count(3, 2), 0);
+    assert_eq!(histogram.total(), 10);
+}
+
+#[test]
+fn test_shape_sampler() {
+    let mut histogram = ShapeHistogram::new();
+    histogram.add(1, 2, 3);
+    histogram.add(2, 1, 1);
+    histogram.add(20, 2, 100);
+    histogram.add(0, 2, 100);
+    let sampler = ShapeSampler::new(&histogram, 10, 10).unwrap();
+    let mut small = 0;
+    for _ in 0..1_000 {
+        let shape = sampler.random_shape();
+        assert!(shape == (1, 2) || shape == (2, 1));
+        if shape == (2, 1) {
+            small += 1;
+        }
+    }
+    assert!(small > 150 && small < 350);
+    assert!(ShapeSampler::new(&histogram, 10, 1).is_ok());
+    assert!(ShapeSampler::new(&histogram, 1, 1).is_err());
+}
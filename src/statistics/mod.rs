@@ -0,0 +1,164 @@
+use rand::Rng;
+use std::cmp::Ordering;
+
+#[cfg(test)]
+mod test;
+
+/// The z value of a two-sided 95% confidence interval of a normal distribution.
+const Z_95: f64 = 1.96;
+
+/// A point estimate with a 95% confidence interval.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub samples: usize,
+    pub mean: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Estimate {
+    /// Estimates the mean of the population with a normal approximation of
+    /// the sampling distribution.
+    pub fn mean(values: &[f64]) -> Estimate {
+        let n = values.len();
+        if n == 0 {
+            return Estimate::empty();
+        }
+        let mean = values.iter().sum::<f64>() / n as f64;
+        let half_width = match n {
+            1 => 0f64,
+            _ => Z_95 * standard_deviation(values) / (n as f64).sqrt(),
+        };
+        Estimate {
+            samples: n,
+            mean,
+            lower: mean - half_width,
+            upper: mean + half_width,
+        }
+    }
+
+    /// Estimates a proportion with the Wilson score interval, which unlike the
+    /// normal approximation behaves at proportions close to zero and one.
+    pub fn proportion(successes: usize, n: usize) -> Estimate {
+        if n == 0 {
+            return Estimate::empty();
+        }
+        let p = successes as f64 / n as f64;
+        let n = n as f64;
+        let z2 = Z_95 * Z_95;
+        let center = (p + z2 / (2f64 * n)) / (1f64 + z2 / n);
+        let half_width = Z_95 / (1f64 + z2 / n) * (p * (1f64 - p) / n + z2 / (4f64 * n * n)).sqrt();
+        Estimate {
+            samples: n as usize,
+            mean: p,
+            lower: center - half_width,
+            upper: center + half_width,
+        }
+    }
+
+    fn empty() -> Estimate {
+        Estimate {
+            samples: 0,
+            mean: 0f64,
+            lower: 0f64,
+            upper: 0f64,
+        }
+    }
+}
+
+/// The sample standard deviation. Zero for less than two values.
+pub fn standard_deviation(values: &[f64]) -> f64 {
+    let n = values.len();
+    if n < 2 {
+        return 0f64;
+    }
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let squares: f64 = values.iter().map(|v| (v - mean) * (v - mean)).sum();
+    (squares / (n - 1) as f64).sqrt()
+}
+
+/// Descriptive statistics of a sample together with a bootstrap confidence
+/// interval of its mean.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub samples: usize,
+    pub mean: f64,
+    pub standard_deviation: f64,
+    pub min: f64,
+    pub p5: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p95: f64,
+    pub max: f64,
+    /// 95% percentile bootstrap interval of the mean.
+    pub mean_lower: f64,
+    pub mean_upper: f64,
+}
+
+impl Summary {
+    /// Summarizes `values`, resampling them `resamples` times with `rng` for
+    /// the confidence interval of the mean. NaN values are left out and do not
+    /// count as samples.
+    pub fn new<R: Rng>(values: &[f64], resamples: usize, rng: &mut R) -> Summary {
+        let mut sorted: Vec<f64> = values.iter().cloned().filter(|v| !v.is_nan()).collect();
+        sorted.sort_by(compare);
+        let mean = match sorted.len() {
+            0 => 0f64,
+            n => sorted.iter().sum::<f64>() / n as f64,
+        };
+        let (mean_lower, mean_upper) = bootstrap_mean_interval(&sorted, resamples, rng);
+        Summary {
+            samples: sorted.len(),
+            mean,
+            standard_deviation: standard_deviation(&sorted),
+            min: percentile(&sorted, 0f64),
+            p5: percentile(&sorted, 5f64),
+            p25: percentile(&sorted, 25f64),
+            median: percentile(&sorted, 50f64),
+            p75: percentile(&sorted, 75f64),
+            p95: percentile(&sorted, 95f64),
+            max: percentile(&sorted, 100f64),
+            mean_lower,
+            mean_upper,
+        }
+    }
+}
+
+/// The `p`th percentile of the ascending `sorted` values, interpolating
+/// linearly between the closest ranks. Zero for no values.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    match sorted.len() {
+        0 => 0f64,
+        1 => sorted[0],
+        n => {
+            let rank = p / 100f64 * (n - 1) as f64;
+            let below = rank.floor() as usize;
+            let above = rank.ceil() as usize;
+            sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+        }
+    }
+}
+
+/// The 2.5th and 97.5th percentile of the means of `resamples` samples drawn
+/// with replacement from `values`.
+fn bootstrap_mean_interval<R: Rng>(values: &[f64], resamples: usize, rng: &mut R) -> (f64, f64) {
+    if values.is_empty() || resamples == 0 {
+        return (0f64, 0f64);
+    }
+    let mut means: Vec<f64> = (0..resamples)
+        .map(|_| {
+            let sum: f64 = (0..values.len())
+                .map(|_| values[rng.gen_range(0, values.len())])
+                .sum();
+            sum / values.len() as f64
+        })
+        .collect();
+    means.sort_by(compare);
+    (percentile(&means, 2.5f64), percentile(&means, 97.5f64))
+}
+
+/// Orders values that are not NaN.
+fn compare(a: &f64, b: &f64) -> Ordering {
+    a.partial_cmp(b).unwrap_or(Ordering::Equal)
+}
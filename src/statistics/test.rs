@@ -0,0 +1,74 @@
+use rand::{SeedableRng, StdRng};
+
+use super::*;
+
+#[test]
+fn test_mean() {
+    let estimate = Estimate::mean(&vec![1.0, 2.0, 3.0]);
+    assert_eq!(estimate.samples, 3);
+    assert_eq!(estimate.mean, 2.0);
+    assert!((estimate.upper - 3.132).abs() < 0.001);
+    assert!((estimate.lower - 0.868).abs() < 0.001);
+    assert_eq!(Estimate::mean(&vec![4.0]).upper, 4.0);
+    assert_eq!(Estimate::mean(&vec![]).samples, 0);
+}
+
+#[test]
+fn test_proportion() {
+    let estimate = Estimate::proportion(10, 10);
+    assert_eq!(estimate.mean, 1.0);
+    assert!((estimate.upper - 1.0).abs() < 1e-9);
+    assert!((estimate.lower - 0.722).abs() < 0.001);
+    let estimate = Estimate::proportion(5, 10);
+    assert!((estimate.lower - 0.237).abs() < 0.001);
+    assert!((estimate.upper - 0.763).abs() < 0.001);
+}
+
+#[test]
+fn test_standard_deviation() {
+    assert_eq!(standard_deviation(&vec![1.0]), 0.0);
+    assert!(
+        (standard_deviation(&vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]) - 2.138).abs() < 0.001
+    );
+}
+
+#[test]
+fn test_percentile() {
+    let sorted = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+    assert_eq!(percentile(&sorted, 0.0), 1.0);
+    assert_eq!(percentile(&sorted, 50.0), 3.0);
+    assert_eq!(percentile(&sorted, 100.0), 5.0);
+    assert_eq!(percentile(&sorted, 10.0), 1.4);
+    assert_eq!(percentile(&vec![], 50.0), 0.0);
+}
+
+#[test]
+fn test_summary() {
+    let seed: &[usize] = &[1, 2, 3, 4];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let values = vec![4.0, 1.0, 3.0, 2.0, 5.0];
+    let summary = Summary::new(&values, 1000, &mut rng);
+    assert_eq!(summary.samples, 5);
+    assert_eq!(summary.mean, 3.0);
+    assert_eq!(summary.median, 3.0);
+    assert_eq!(summary.min, 1.0);
+    assert_eq!(summary.max, 5.0);
+    assert!(summary.mean_lower < 3.0 && summary.mean_lower >= 1.0);
+    assert!(summary.mean_upper > 3.0 && summary.mean_upper <= 5.0);
+    let constant = Summary::new(&vec![2.0, 2.0], 100, &mut rng);
+    assert_eq!((constant.mean_lower, constant.mean_upper), (2.0, 2.0));
+    assert_eq!(constant.standard_deviation, 0.0);
+}
+
+#[test]
+fn test_summary_without_nan() {
+    let seed: &[usize] = &[1, 2, 3, 4];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let summary = Summary::new(&vec![1.0, f64::NAN, 3.0], 100, &mut rng);
+    assert_eq!(summary.samples, 2);
+    assert_eq!(summary.mean, 2.0);
+    assert_eq!(summary.max, 3.0);
+    let summary = Summary::new(&vec![f64::NAN], 100, &mut rng);
+    assert_eq!(summary.samples, 0);
+    assert_eq!(summary.mean, 0.0);
+}
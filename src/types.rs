@@ -1,9 +1,15 @@
+use budget::SearchStatus;
+
 /// An ordered multi-set of natural numbers represented as a vector. The order
 /// has no meaning apart from indexing the elements so they can be identified.
 pub type Set = Vec<u64>;
 
 pub type Partition = Vec<Set>;
 
+/// Pairs of indices of the parts of an input partition and of an output
+/// partition, matching each input part to the output part with the same sum.
+pub type Pairing = Vec<(usize, usize)>;
+
 /// An abstract representation of a Bitcoin transaction as two sets of natural
 /// numbers.
 #[derive(Serialize, Deserialize)]
@@ -26,10 +32,34 @@ pub trait Filter<T> {
 pub struct Run {
     pub num_transactions: u64,
     pub num_inputs_per_transaction: u64,
+    /// How the sub-transactions were shuffled, as passed to `cja auto -S`.
+    /// `None` in results written before it was recorded.
+    #[serde(default)]
+    pub shuffled: Option<String>,
+    /// The shapes file the numbers of inputs and outputs of the
+    /// sub-transactions were drawn from with `cja auto --shapes`, instead of
+    /// `num_inputs_per_transaction` inputs and two outputs each.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shapes: Option<String>,
     pub original_transactions: Vec<Transaction>,
     pub in_coins: Vec<u64>,
     pub out_coins: Vec<u64>,
     pub partition_tuples: Vec<(Partition, Partition)>,
+    /// The pairing of the parts of each entry of `partition_tuples`, at the
+    /// same index. Empty in results written before pairings were recorded.
+    #[serde(default)]
+    pub pairings: Vec<Pairing>,
     pub duration_secs: u64,
     pub duration_nano: u32,
+    /// Whether `partition_tuples` holds all mappings or the search was cut
+    /// short by its budget.
+    #[serde(default)]
+    pub search_status: SearchStatus,
+    /// The id of the on-chain transaction analyzed by `cja chain`. `None` for
+    /// generated runs, whose files therefore don't change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub txid: Option<String>,
+    /// The height of the block that contains `txid`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u64>,
 }
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use blockchain::{Block, Transaction};
+
+#[cfg(test)]
+mod test;
+
+/// OP_RETURN, outputs whose script starts with it can never be spent.
+const OP_RETURN: u8 = 0x6a;
+
+/// The coins that are unspent after applying blocks in the order of the
+/// chain. On the main chain this holds many millions of coins.
+#[derive(Default)]
+pub struct UtxoSet {
+    coins: HashMap<([u8; 32], u32), u64>,
+}
+
+impl UtxoSet {
+    pub fn new() -> UtxoSet {
+        UtxoSet::default()
+    }
+
+    /// Spends the inputs and adds the outputs of the transactions of a block.
+    /// Like bitcoind, unspendable outputs are not added. Returns the number
+    /// of inputs that spend unknown coins, which happens if blocks are
+    /// missing or not applied in order.
+    pub fn apply_block(&mut self, block: &Block) -> usize {
+        block
+            .transactions
+            .iter()
+            .map(|transaction| self.apply_transaction(transaction))
+            .sum()
+    }
+
+    /// Like `apply_block` for a single transaction.
+    pub fn apply_transaction(&mut self, transaction: &Transaction) -> usize {
+        let mut missing = 0;
+        for input in transaction.inputs.iter() {
+            if input.is_coinbase() {
+                continue;
+            }
+            let outpoint = (input.previous_output.hash, input.previous_output.index);
+            if self.coins.remove(&outpoint).is_none() {
+                missing += 1;
+            }
+        }
+        for (index, output) in transaction.outputs.iter().enumerate() {
+            if output.value < 0 || output.pk_script.first() == Some(&OP_RETURN) {
+                continue;
+            }
+            self.coins
+                .insert((transaction.txid, index as u32), output.value as u64);
+        }
+        missing
+    }
+
+    /// The values of the coins spent by a transaction that is not applied
+    /// yet, if they are all known. Coinbase inputs have no value.
+    pub fn input_values(&self, transaction: &Transaction) -> Option<Vec<u64>> {
+        transaction
+            .inputs
+            .iter()
+            .filter(|input| !input.is_coinbase())
+            .map(|input| {
+                self.coins
+                    .get(&(input.previous_output.hash, input.previous_output.index))
+                    .cloned()
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coins.is_empty()
+    }
+
+    /// The values of the unspent coins, in no particular order.
+    pub fn values<'a>(&'a self) -> impl Iterator<Item = u64> + 'a {
+        self.coins.values().cloned()
+    }
+}
+
+/// The values of only the coins spent by some transactions, collected from
+/// the transactions that created them. This needs far less memory than a
+/// `UtxoSet` to resolve the input values of a few transactions.
+#[derive(Default)]
+pub struct PrevoutValues {
+    /// The output indices to collect of each transaction.
+    wanted: HashMap<[u8; 32], Vec<u32>>,
+    values: HashMap<([u8; 32], u32), u64>,
+    count: usize,
+}
+
+impl PrevoutValues {
+    pub fn new<'a, I: IntoIterator<Item = &'a Transaction>>(transactions: I) -> PrevoutValues {
+        let mut prevouts = PrevoutValues::default();
+        for transaction in transactions {
+            for input in transaction
+                .inputs
+                .iter()
+                .filter(|input| !input.is_coinbase())
+            {
+                let indices = prevouts
+                    .wanted
+                    .entry(input.previous_output.hash)
+                    .or_insert_with(Vec::new);
+                if !indices.contains(&input.previous_output.index) {
+                    indices.push(input.previous_output.index);
+                    prevouts.count += 1;
+                }
+            }
+        }
+        prevouts
+    }
+
+    /// Records the values of the wanted outputs of a transaction.
+    pub fn add_transaction(&mut self, transaction: &Transaction) {
+        if let Some(indices) = self.wanted.get(&transaction.txid) {
+            for &index in indices.iter() {
+                if let Some(output) = transaction.outputs.get(index as usize) {
+                    self.values
+                        .insert((transaction.txid, index), output.value.max(0) as u64);
+                }
+            }
+        }
+    }
+
+    /// Whether the values of all wanted outputs are known.
+    pub fn is_complete(&self) -> bool {
+        self.values.len() == self.count
+    }
+
+    /// Like `UtxoSet::input_values`.
+    pub fn input_values(&self, transaction: &Transaction) -> Option<Vec<u64>> {
+        transaction
+            .inputs
+            .iter()
+            .filter(|input| !input.is_coinbase())
+            .map(|input| {
+                self.values
+                    .get(&(input.previous_output.hash, input.previous_output.index))
+                    .cloned()
+            })
+            .collect()
+    }
+}
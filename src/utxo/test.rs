@@ -0,0 +1,108 @@
+use super::*;
+use blockchain::{parse_block_header, Outpoint, Transaction, TransactionInput, TransactionOutput};
+
+fn input(hash: u8, index: u32) -> TransactionInput {
+    TransactionInput {
+        previous_output: Outpoint {
+            hash: [hash; 32],
+            index,
+        },
+        sequence: 0,
+        script: Vec::new(),
+        witness: Vec::new(),
+    }
+}
+
+fn output(value: i64) -> TransactionOutput {
+    TransactionOutput {
+        value,
+        pk_script: vec![0x76],
+    }
+}
+
+fn block(transactions: Vec<Transaction>) -> Block {
+    Block {
+        header: parse_block_header(&[0u8; 80]).to_full_result().unwrap(),
+        transactions,
+    }
+}
+
+fn transaction(
+    txid: u8,
+    inputs: Vec<TransactionInput>,
+    outputs: Vec<TransactionOutput>,
+) -> Transaction {
+    Transaction {
+        txid: [txid; 32],
+        version: 1,
+        lock_time: 0,
+        inputs,
+        outputs,
+    }
+}
+
+#[test]
+fn test_apply_blocks() {
+    let mut utxos = UtxoSet::new();
+    let coinbase = input(0, 0xffff_ffff);
+    let mut unspendable = output(0);
+    unspendable.pk_script = vec![OP_RETURN, 1, 2];
+    assert_eq!(
+        utxos.apply_block(&block(vec![
+            transaction(1, vec![coinbase], vec![output(50), output(20), unspendable]),
+            // Spends an output of the same block.
+            transaction(2, vec![input(1, 1)], vec![output(15), output(5)]),
+        ])),
+        0
+    );
+    let mut values: Vec<u64> = utxos.values().collect();
+    values.sort();
+    assert_eq!(values, vec![5, 15, 50]);
+
+    assert_eq!(
+        utxos.apply_block(&block(vec![transaction(
+            3,
+            vec![input(2, 0), input(1, 2), input(9, 0)],
+            vec![output(14)]
+        )])),
+        2
+    );
+    let mut values: Vec<u64> = utxos.values().collect();
+    values.sort();
+    assert_eq!(values, vec![5, 14, 50]);
+    assert_eq!(utxos.len(), 3);
+}
+
+#[test]
+fn test_input_values() {
+    let mut utxos = UtxoSet::new();
+    utxos.apply_block(&block(vec![transaction(
+        1,
+        vec![input(0, 0xffff_ffff)],
+        vec![output(50), output(20)],
+    )]));
+    let spending = transaction(2, vec![input(1, 1), input(1, 0)], vec![output(60)]);
+    assert_eq!(utxos.input_values(&spending), Some(vec![20, 50]));
+    assert_eq!(utxos.apply_transaction(&spending), 0);
+    assert_eq!(utxos.input_values(&spending), None);
+    let coinbase = transaction(3, vec![input(0, 0xffff_ffff)], vec![output(50)]);
+    assert_eq!(utxos.input_values(&coinbase), Some(vec![]));
+}
+
+#[test]
+fn test_prevout_values() {
+    let funding = transaction(
+        1,
+        vec![input(0, 0xffff_ffff)],
+        vec![output(50), output(20), output(30)],
+    );
+    let spending = transaction(2, vec![input(1, 2), input(1, 0)], vec![output(70)]);
+    let mut prevouts = PrevoutValues::new(vec![&spending]);
+    assert!(!prevouts.is_complete());
+    assert_eq!(prevouts.input_values(&spending), None);
+    prevouts.add_transaction(&transaction(3, vec![], vec![output(5)]));
+    prevouts.add_transaction(&funding);
+    assert!(prevouts.is_complete());
+    assert_eq!(prevouts.input_values(&spending), Some(vec![30, 50]));
+    assert_eq!(prevouts.values.len(), 2);
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::fmt;
+
+use types::Set;
+
+#[cfg(test)]
+mod test;
+
+/// Satoshis per bitcoin.
+const SATOSHIS_PER_BITCOIN: u64 = 100_000_000;
+
+/// Parses an amount in satoshis, or in bitcoin if it contains a decimal point,
+/// e.g. `150000` or `0.0015`. Bitcoin amounts are converted exactly and may
+/// have at most eight decimals.
+pub fn parse_amount(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (whole, fraction) = match value.find('.') {
+        Some(point) => (&value[..point], &value[point + 1..]),
+        None => return value.parse::<u64>().map_err(|e| e.to_string()),
+    };
+    if fraction.len() > 8 {
+        return Err("more than eight decimals".to_string());
+    }
+    if (whole.is_empty() && fraction.is_empty())
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err("invalid bitcoin amount".to_string());
+    }
+    let whole = match whole {
+        "" => 0,
+        whole => whole.parse::<u64>().map_err(|e| e.to_string())?,
+    };
+    let fraction = format!("{:0<8}", fraction)
+        .parse::<u64>()
+        .map_err(|e| e.to_string())?;
+    whole
+        .checked_mul(SATOSHIS_PER_BITCOIN)
+        .and_then(|sats| sats.checked_add(fraction))
+        .ok_or_else(|| "amount is too large".to_string())
+}
+
+/// The coins of a transaction prepared for the analysis.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ValidatedTransaction {
+    pub inputs: Set,
+    pub outputs: Set,
+    /// The input sum minus the output sum.
+    pub fee: u64,
+    /// Indices of the inputs with a value of zero, which were removed.
+    pub zero_inputs: Vec<usize>,
+    /// Indices of the outputs with a value of zero, e.g. `OP_RETURN` outputs,
+    /// which were removed.
+    pub zero_outputs: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    NoInputs,
+    NoOutputs,
+    AmountOverflow,
+    OutputsExceedInputs { input_sum: u64, output_sum: u64 },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::NoInputs => write!(f, "The transaction has no inputs of value"),
+            ValidationError::NoOutputs => write!(f, "The transaction has no outputs of value"),
+            ValidationError::AmountOverflow => write!(f, "The sum of the amounts is too large"),
+            ValidationError::OutputsExceedInputs {
+                input_sum,
+                output_sum,
+            } => write!(
+                f,
+                "The outputs sum up to {} which is more than the inputs, {}",
+                output_sum, input_sum
+            ),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Checks that `outputs` do not spend more than `inputs` and removes coins
+/// with a value of zero. Zero is a subset sum of every set, so such coins
+/// would fit into any part of any partition and multiply the mappings without
+/// revealing anything.
+pub fn validate_transaction(
+    inputs: &Set,
+    outputs: &Set,
+) -> Result<ValidatedTransaction, ValidationError> {
+    let (inputs, zero_inputs) = remove_zero_coins(inputs);
+    let (outputs, zero_outputs) = remove_zero_coins(outputs);
+    if inputs.is_empty() {
+        return Err(ValidationError::NoInputs);
+    }
+    if outputs.is_empty() {
+        return Err(ValidationError::NoOutputs);
+    }
+    let input_sum = checked_sum(&inputs).ok_or(ValidationError::AmountOverflow)?;
+    let output_sum = checked_sum(&outputs).ok_or(ValidationError::AmountOverflow)?;
+    if output_sum > input_sum {
+        return Err(ValidationError::OutputsExceedInputs {
+            input_sum,
+            output_sum,
+        });
+    }
+    Ok(ValidatedTransaction {
+        inputs,
+        outputs,
+        fee: input_sum - output_sum,
+        zero_inputs,
+        zero_outputs,
+    })
+}
+
+fn remove_zero_coins(coins: &Set) -> (Set, Vec<usize>) {
+    let zeros = coins
+        .iter()
+        .enumerate()
+        .filter(|&(_, &coin)| coin == 0)
+        .map(|(index, _)| index)
+        .collect();
+    (
+        coins.iter().filter(|&&coin| coin > 0).copied().collect(),
+        zeros,
+    )
+}
+
+fn checked_sum(coins: &Set) -> Option<u64> {
+    coins
+        .iter()
+        .try_fold(0u64, |sum, &coin| sum.checked_add(coin))
+}
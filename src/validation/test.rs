@@ -0,0 +1,44 @@
+use super::*;
+
+#[test]
+fn test_parse_amount() {
+    assert_eq!(parse_amount("150000"), Ok(150_000));
+    assert_eq!(parse_amount("0.0015"), Ok(150_000));
+    assert_eq!(parse_amount(".5"), Ok(50_000_000));
+    assert_eq!(parse_amount("21."), Ok(2_100_000_000));
+    assert_eq!(parse_amount(" 1.00000001 "), Ok(100_000_001));
+    assert!(parse_amount("0.000000001").is_err());
+    assert!(parse_amount("1.-5").is_err());
+    assert!(parse_amount(".").is_err());
+    assert!(parse_amount("abc").is_err());
+    assert!(parse_amount("1000000000000.0").is_err());
+}
+
+#[test]
+fn test_validate_transaction() {
+    assert_eq!(
+        validate_transaction(&vec![5, 0, 3], &vec![0, 7]),
+        Ok(ValidatedTransaction {
+            inputs: vec![5, 3],
+            outputs: vec![7],
+            fee: 1,
+            zero_inputs: vec![1],
+            zero_outputs: vec![0],
+        })
+    );
+    assert_eq!(
+        validate_transaction(&vec![5], &vec![3, 4]),
+        Err(ValidationError::OutputsExceedInputs {
+            input_sum: 5,
+            output_sum: 7,
+        })
+    );
+    assert_eq!(
+        validate_transaction(&vec![0], &vec![0]),
+        Err(ValidationError::NoInputs)
+    );
+    assert_eq!(
+        validate_transaction(&vec![u64::MAX, 1], &vec![1]),
+        Err(ValidationError::AmountOverflow)
+    );
+}